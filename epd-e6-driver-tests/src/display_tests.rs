@@ -0,0 +1,1921 @@
+use embedded_graphics::image::{GetPixel, Image, ImageRaw};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::SpiDevice;
+use epd_e6_driver::display::Error;
+use epd_e6_driver::e6_display::CommandCode;
+use epd_e6_driver::prelude::*;
+use epd_e6_driver::testing::{
+    Emulator, EmulatorBusyPin, EmulatorDcPin, EmulatorDelay, EmulatorResetPin, EmulatorSpi,
+    EmulatorTimings, Window,
+};
+use std::cell::Cell;
+use std::future::Future;
+use std::iter;
+use std::pin::pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+const WIDTH: u16 = 800;
+const HEIGHT: u16 = 480;
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+fn frame_buffer() -> Nibbles<Vec<u8>, E6Color> {
+    let len = WIDTH as usize * HEIGHT as usize;
+    Nibbles::new(vec![0u8; underlying_data_len(len)], len)
+}
+
+type TestDisplay = E6Display<
+    FourWire<EmulatorSpi, EmulatorDcPin>,
+    EmulatorResetPin,
+    EmulatorBusyPin,
+    EmulatorDelay,
+    Vec<u8>,
+>;
+
+type AsyncTestDisplay = AsyncE6Display<
+    FourWire<EmulatorSpi, EmulatorDcPin>,
+    EmulatorResetPin,
+    EmulatorBusyPin,
+    EmulatorDelay,
+    Vec<u8>,
+>;
+
+fn blocking_display(emulator: &Emulator) -> TestDisplay {
+    E6Display::new(
+        WIDTH,
+        HEIGHT,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    )
+}
+
+fn async_display(emulator: &Emulator) -> AsyncTestDisplay {
+    AsyncE6Display::new(
+        WIDTH,
+        HEIGHT,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    )
+}
+
+const REFRESH_COMMANDS: [CommandCode; 5] = [
+    CommandCode::DTM1,
+    CommandCode::DSP,
+    CommandCode::PON,
+    CommandCode::DRF,
+    CommandCode::POF,
+];
+
+fn assert_commands(emulator: &Emulator, expected: &[CommandCode]) {
+    let expected: Vec<u8> = expected.iter().map(|code| *code as u8).collect();
+    assert_eq!(emulator.command_codes(), expected);
+}
+
+#[test]
+fn blocking_initialize_configures_resolution() {
+    let emulator = Emulator::new();
+    let mut display = blocking_display(&emulator);
+    display.initialize().unwrap();
+
+    let commands = emulator.commands();
+    assert!(commands[0].is(CommandCode::INIT));
+    assert_eq!(emulator.resolution(), (WIDTH, HEIGHT));
+    assert!(emulator.violations().is_empty());
+}
+
+#[test]
+fn blocking_refresh_shows_frame_buffer() {
+    let emulator = Emulator::new();
+    let mut display = blocking_display(&emulator);
+    display.initialize().unwrap();
+    emulator.clear_commands();
+
+    display
+        .update(iter::repeat_n(
+            E6Color::White,
+            WIDTH as usize * HEIGHT as usize,
+        ))
+        .unwrap();
+    display
+        .partial_update(iter::repeat(E6Color::Red), 10..=19, 5..=9)
+        .unwrap();
+    display.refresh().unwrap();
+
+    assert_commands(&emulator, &REFRESH_COMMANDS);
+    assert_eq!(emulator.refresh_count(), 1);
+    assert!(!emulator.is_powered());
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::White));
+    assert_eq!(emulator.pixel(10, 5), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(19, 9), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(20, 9), Some(E6Color::White));
+}
+
+#[test]
+fn packed_image_draws_with_image_raw() {
+    let colors = [
+        E6Color::Black,
+        E6Color::White,
+        E6Color::Yellow,
+        E6Color::Red,
+        E6Color::Blue,
+        E6Color::Green,
+    ];
+    let mut asset = Nibbles::new(vec![0u8; 3], colors.len());
+    for (index, color) in colors.iter().enumerate() {
+        asset.set(index, *color);
+    }
+    let image = ImageRaw::<E6Color>::new(asset.as_underlying_data(), 2);
+    let pixels: Vec<E6Color> = (0..6)
+        .filter_map(|index| image.pixel(Point::new(index % 2, index / 2)))
+        .collect();
+    assert_eq!(pixels, colors);
+
+    let emulator = Emulator::new();
+    let mut display = blocking_display(&emulator);
+    display.initialize().unwrap();
+    display.clear(E6Color::White).unwrap();
+    Image::new(&image, Point::new(10, 20))
+        .draw(&mut display)
+        .unwrap();
+    display.refresh().unwrap();
+
+    for (index, color) in colors.iter().enumerate() {
+        let (x, y) = (10 + index as u16 % 2, 20 + index as u16 / 2);
+        assert_eq!(emulator.pixel(x, y), Some(*color));
+    }
+    assert_eq!(emulator.pixel(12, 20), Some(E6Color::White));
+}
+
+#[test]
+fn async_refresh_shows_frame_buffer() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    block_on(async {
+        display.initialize().await.unwrap();
+        display
+            .update(iter::repeat_n(
+                E6Color::Blue,
+                WIDTH as usize * HEIGHT as usize,
+            ))
+            .await
+            .unwrap();
+        display
+            .partial_update(iter::repeat(E6Color::Green), 0..=1, 0..=0)
+            .await
+            .unwrap();
+        emulator.clear_commands();
+        display.refresh().await.unwrap();
+    });
+
+    assert_commands(&emulator, &REFRESH_COMMANDS);
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(1, 0), Some(E6Color::Green));
+    assert_eq!(emulator.pixel(2, 0), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(WIDTH - 1, HEIGHT - 1), Some(E6Color::Blue));
+}
+
+#[test]
+fn blocking_partial_refresh_updates_only_window() {
+    let emulator = Emulator::new();
+    let mut display = blocking_display(&emulator);
+    display.initialize().unwrap();
+    display
+        .update(iter::repeat_n(
+            E6Color::White,
+            WIDTH as usize * HEIGHT as usize,
+        ))
+        .unwrap();
+    display.refresh().unwrap();
+
+    display
+        .update(iter::repeat_n(
+            E6Color::Red,
+            WIDTH as usize * HEIGHT as usize,
+        ))
+        .unwrap();
+    emulator.clear_commands();
+    display.partial_refresh(11..=20, 100..=109).unwrap();
+
+    assert_commands(
+        &emulator,
+        &[
+            CommandCode::PTL,
+            CommandCode::DTM1,
+            CommandCode::DSP,
+            CommandCode::PON,
+            CommandCode::DRF,
+            CommandCode::POF,
+        ],
+    );
+    let commands = emulator.commands();
+    assert_eq!(commands[0].data, [0, 10, 0, 21, 0, 100, 0, 109, 0x01]);
+    assert_eq!(commands[1].data.len(), 6 * 10);
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(10, 100), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(21, 109), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(9, 100), Some(E6Color::White));
+    assert_eq!(emulator.pixel(22, 100), Some(E6Color::White));
+    assert_eq!(emulator.pixel(10, 110), Some(E6Color::White));
+
+    emulator.clear_commands();
+    display.refresh().unwrap();
+    let commands = emulator.commands();
+    assert!(commands[0].is(CommandCode::PTL));
+    assert_eq!(commands[0].data, [0, 0, 0x03, 0x1F, 0, 0, 0x01, 0xDF, 0x01]);
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Red));
+}
+
+#[test]
+#[allow(clippy::reversed_empty_ranges)]
+fn partial_refresh_rejects_reversed_ranges_and_odd_widths() {
+    let emulator = Emulator::new();
+    let mut display = blocking_display(&emulator);
+    display.initialize().unwrap();
+    let commands = emulator.command_codes().len();
+    display.partial_refresh(5..=4, 0..=3).unwrap();
+    display.partial_refresh(0..=3, 7..=2).unwrap();
+    assert_eq!(emulator.command_codes().len(), commands);
+
+    let profile = PanelProfile::SPECTRA6_7IN3.with_resolution(15, 10);
+    let mut display = profile_display(&Emulator::new(), profile);
+    let odd = |result| {
+        matches!(
+            result,
+            Err(Error::UnsupportedResolution {
+                width: 15,
+                height: 10
+            })
+        )
+    };
+    assert!(odd(display.partial_refresh(0..=3, 0..=3)));
+    assert!(odd(display.update_packed(&[0; 80])));
+    assert!(odd(display.partial_update_packed(&[0; 2], 0..=3, 0..=0)));
+}
+
+#[test]
+fn async_partial_refresh_updates_only_window() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    block_on(async {
+        display.initialize().await.unwrap();
+        display
+            .update(iter::repeat_n(
+                E6Color::White,
+                WIDTH as usize * HEIGHT as usize,
+            ))
+            .await
+            .unwrap();
+        display.refresh().await.unwrap();
+        display
+            .partial_update(iter::repeat(E6Color::Green), 0..=WIDTH - 1, 0..=HEIGHT - 1)
+            .await
+            .unwrap();
+        emulator.clear_commands();
+        display.partial_refresh(790..=900, 470..=500).await.unwrap();
+    });
+
+    let commands = emulator.commands();
+    assert_eq!(
+        commands[0].data,
+        [0x03, 0x16, 0x03, 0x1F, 0x01, 0xD6, 0x01, 0xDF, 0x01]
+    );
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(790, 470), Some(E6Color::Green));
+    assert_eq!(emulator.pixel(WIDTH - 1, HEIGHT - 1), Some(E6Color::Green));
+    assert_eq!(emulator.pixel(789, 470), Some(E6Color::White));
+    assert_eq!(emulator.pixel(790, 469), Some(E6Color::White));
+}
+
+#[test]
+fn blocking_refresh_wakes_sleeping_panel() {
+    let emulator = Emulator::new();
+    let mut display = blocking_display(&emulator);
+    assert_eq!(display.power_state(), PowerState::Uninitialized);
+    display.initialize().unwrap();
+    assert_eq!(display.power_state(), PowerState::Awake);
+
+    display.sleep().unwrap();
+    assert_eq!(display.power_state(), PowerState::DeepSleep);
+    assert!(emulator.is_sleeping());
+    assert!(emulator.commands().last().unwrap().is(CommandCode::DSLP));
+
+    display
+        .update(iter::repeat_n(
+            E6Color::Yellow,
+            WIDTH as usize * HEIGHT as usize,
+        ))
+        .unwrap();
+    emulator.clear_commands();
+    display.refresh().unwrap();
+
+    assert_eq!(display.power_state(), PowerState::Awake);
+    assert!(!emulator.is_sleeping());
+    assert!(emulator.commands()[0].is(CommandCode::INIT));
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Yellow));
+}
+
+#[test]
+fn async_sleep_and_wake() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    block_on(async {
+        display.initialize().await.unwrap();
+        display.sleep().await.unwrap();
+        assert!(emulator.is_sleeping());
+        display.wake().await.unwrap();
+    });
+
+    assert_eq!(display.power_state(), PowerState::Awake);
+    assert!(!emulator.is_sleeping());
+    assert_eq!(emulator.resolution(), (WIDTH, HEIGHT));
+    assert!(emulator.violations().is_empty());
+}
+
+#[test]
+fn typestate_panel_lifecycle() {
+    let emulator = Emulator::new();
+    let panel = Panel::new(blocking_display(&emulator));
+    let mut panel = panel.initialize().map_err(|(_, error)| error).unwrap();
+
+    panel.clear(E6Color::White).unwrap();
+    Rectangle::new(Point::new(4, 2), Size::new(2, 3))
+        .into_styled(PrimitiveStyle::with_fill(E6Color::Blue))
+        .draw(&mut panel)
+        .unwrap();
+    let panel = panel.sleep().map_err(|(_, error)| error).unwrap();
+    assert!(emulator.is_sleeping());
+
+    let mut panel = panel.wake().map_err(|(_, error)| error).unwrap();
+    panel.refresh().unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(4, 2), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(5, 4), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(6, 4), Some(E6Color::White));
+    assert_eq!(panel.release().power_state(), PowerState::Awake);
+}
+
+/// Display that records which drawing methods it was called with.
+#[derive(Default)]
+struct RecordingDisplay {
+    calls: Vec<&'static str>,
+}
+
+impl Display<E6Color> for RecordingDisplay {
+    fn width(&self) -> u16 {
+        8
+    }
+
+    fn height(&self) -> u16 {
+        4
+    }
+}
+
+impl BlockingDisplay<E6Color> for RecordingDisplay {
+    fn initialize(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn update(&mut self, _iter: impl IntoIterator<Item = E6Color>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn wake(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn power_state(&self) -> PowerState {
+        PowerState::Awake
+    }
+}
+
+impl AsyncDisplay<E6Color> for RecordingDisplay {
+    async fn initialize(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update(&mut self, _iter: impl IntoIterator<Item = E6Color>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn refresh(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn sleep(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn wake(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn power_state(&self) -> PowerState {
+        PowerState::Awake
+    }
+}
+
+impl OriginDimensions for RecordingDisplay {
+    fn size(&self) -> Size {
+        Size::new(8, 4)
+    }
+}
+
+impl DrawTarget for RecordingDisplay {
+    type Color = E6Color;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.calls.push("draw_iter");
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, _area: &Rectangle, _colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.calls.push("fill_contiguous");
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, _area: &Rectangle, _color: Self::Color) -> Result<(), Self::Error> {
+        self.calls.push("fill_solid");
+        Ok(())
+    }
+
+    fn clear(&mut self, _color: Self::Color) -> Result<(), Self::Error> {
+        self.calls.push("clear");
+        Ok(())
+    }
+}
+
+fn draw_with_every_method(target: &mut impl DrawTarget<Color = E6Color, Error = Error>) {
+    let area = Rectangle::new(Point::new(1, 1), Size::new(2, 2));
+    target.clear(E6Color::White).unwrap();
+    target.fill_solid(&area, E6Color::Red).unwrap();
+    target
+        .fill_contiguous(&area, iter::repeat(E6Color::Blue))
+        .unwrap();
+    target
+        .draw_iter(iter::once(Pixel(Point::zero(), E6Color::Green)))
+        .unwrap();
+}
+
+#[test]
+fn typestate_fast_fills_test() {
+    let expected = ["clear", "fill_solid", "fill_contiguous", "draw_iter"];
+
+    let panel = Panel::<_, _, E6Color>::new(RecordingDisplay::default());
+    let mut panel = panel.initialize().map_err(|(_, error)| error).unwrap();
+    draw_with_every_method(&mut panel);
+    assert_eq!(panel.release().calls, expected);
+
+    let panel = AsyncPanel::<_, _, E6Color>::new(RecordingDisplay::default());
+    let mut panel = block_on(panel.initialize())
+        .map_err(|(_, error)| error)
+        .unwrap();
+    draw_with_every_method(&mut panel);
+    assert_eq!(panel.release().calls, expected);
+}
+
+#[test]
+fn async_typestate_panel_lifecycle() {
+    let emulator = Emulator::new();
+    let display = block_on(async {
+        let panel = AsyncPanel::new(async_display(&emulator));
+        let mut panel = panel
+            .initialize()
+            .await
+            .map_err(|(_, error)| error)
+            .unwrap();
+        panel
+            .partial_update(iter::repeat(E6Color::Red), 0..=1, 0..=0)
+            .await
+            .unwrap();
+        panel.refresh().await.unwrap();
+        let panel = panel.sleep().await.map_err(|(_, error)| error).unwrap();
+        panel.release()
+    });
+
+    assert_eq!(display.power_state(), PowerState::DeepSleep);
+    assert_eq!(emulator.pixel(1, 0), Some(E6Color::Red));
+}
+
+fn profile_display(emulator: &Emulator, profile: PanelProfile) -> TestDisplay {
+    E6Display::with_profile(
+        profile,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        Nibbles::new(vec![0u8; underlying_data_len(profile.len())], profile.len()),
+    )
+}
+
+#[test]
+fn profile_resolution_is_sent_as_tres() {
+    let emulator = Emulator::new();
+    let mut display = profile_display(&emulator, PanelProfile::SPECTRA6_4IN0);
+    display.initialize().unwrap();
+
+    let tres = emulator
+        .commands()
+        .into_iter()
+        .find(|record| record.is(CommandCode::TRES))
+        .unwrap();
+    assert_eq!(tres.data, [0x01, 0x90, 0x02, 0x58]);
+    assert_eq!(emulator.resolution(), (400, 600));
+    assert_eq!((display.width(), display.height()), (400, 600));
+}
+
+#[test]
+fn custom_profile_resolution_and_timings() {
+    let emulator = Emulator::new();
+    let profile = PanelProfile::SPECTRA6_7IN3
+        .with_resolution(640, 384)
+        .with_reset_delay_ms(5)
+        .with_busy_policy(BusyPolicy::new(10, 30_000));
+    let mut display = profile_display(&emulator, profile);
+    display.initialize().unwrap();
+    assert_eq!(emulator.resolution(), (640, 384));
+
+    display
+        .update(iter::repeat_n(E6Color::Green, profile.len()))
+        .unwrap();
+    display.refresh().unwrap();
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(639, 383), Some(E6Color::Green));
+}
+
+type DualTestDisplay = E6DualDisplay<
+    FourWire<DualSpi<EmulatorSpi, EmulatorSpi>, EmulatorDcPin>,
+    EmulatorResetPin,
+    EmulatorBusyPin,
+    EmulatorDelay,
+    Vec<u8>,
+>;
+
+const DUAL_PROFILE: PanelProfile = PanelProfile::SPECTRA6_13IN3.with_resolution(40, 8);
+
+fn dual_display(emulator: &Emulator) -> DualTestDisplay {
+    E6DualDisplay::with_profile(
+        DUAL_PROFILE,
+        emulator.controller_spi(0),
+        emulator.controller_spi(1),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        Nibbles::new(
+            vec![0u8; underlying_data_len(DUAL_PROFILE.len())],
+            DUAL_PROFILE.len(),
+        ),
+    )
+}
+
+#[test]
+fn dual_display_sends_vendor_tres_to_both_controllers() {
+    let emulator = Emulator::with_controllers(2);
+    let profile = PanelProfile::SPECTRA6_13IN3;
+    let mut display = E6DualDisplay::with_profile(
+        profile,
+        emulator.controller_spi(0),
+        emulator.controller_spi(1),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        Nibbles::new(vec![0u8; underlying_data_len(profile.len())], profile.len()),
+    );
+    display.initialize().unwrap();
+    for index in 0..2 {
+        let tres = emulator
+            .controller(index)
+            .commands
+            .into_iter()
+            .find(|record| record.is(CommandCode::TRES))
+            .unwrap();
+        assert_eq!(tres.data, [0x04, 0xB0, 0x03, 0x20]);
+    }
+    assert_eq!((display.width(), display.height()), (1200, 1600));
+    assert!(
+        PanelProfile::SPECTRA6_13IN3
+            .with_resolution(40, 8)
+            .tres
+            .is_none()
+    );
+}
+
+#[test]
+fn dual_display_splits_frame_between_controllers() {
+    let emulator = Emulator::with_controllers(2);
+    let mut display = dual_display(&emulator);
+    display.initialize().unwrap();
+    for index in 0..2 {
+        let controller = emulator.controller(index);
+        assert_eq!((controller.width, controller.height), (20, 8));
+    }
+    assert!(
+        emulator
+            .controller(0)
+            .commands
+            .iter()
+            .any(|record| record.is(CommandCode::EN_BUF))
+    );
+    assert!(
+        !emulator
+            .controller(1)
+            .commands
+            .iter()
+            .any(|record| record.is(CommandCode::EN_BUF))
+    );
+
+    display.clear(E6Color::White).unwrap();
+    Rectangle::new(Point::new(16, 2), Size::new(8, 4))
+        .into_styled(PrimitiveStyle::with_fill(E6Color::Red))
+        .draw(&mut display)
+        .unwrap();
+    display.refresh().unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.resolution(), (40, 8));
+    assert_eq!(emulator.controller(0).refresh_count, 1);
+    assert_eq!(emulator.controller(1).refresh_count, 1);
+    assert_eq!(emulator.pixel(15, 2), Some(E6Color::White));
+    assert_eq!(emulator.pixel(16, 2), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(19, 5), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(20, 2), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(23, 5), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(24, 2), Some(E6Color::White));
+    assert_eq!(emulator.pixel(39, 7), Some(E6Color::White));
+}
+
+#[test]
+fn dual_display_partial_refresh_touches_owning_controller() {
+    let emulator = Emulator::with_controllers(2);
+    let mut display = dual_display(&emulator);
+    display.initialize().unwrap();
+    display.clear(E6Color::White).unwrap();
+    display.refresh().unwrap();
+
+    display.clear(E6Color::Blue).unwrap();
+    display.partial_refresh(26..=29, 1..=2).unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.controller(0).refresh_count, 1);
+    assert_eq!(emulator.controller(1).refresh_count, 2);
+    assert_eq!(
+        emulator.controller(1).window,
+        Some(Window {
+            x_start: 6,
+            x_end: 9,
+            y_start: 1,
+            y_end: 2,
+        })
+    );
+    assert_eq!(emulator.pixel(26, 1), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(29, 2), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(25, 1), Some(E6Color::White));
+    assert_eq!(emulator.pixel(6, 1), Some(E6Color::White));
+
+    display.sleep().unwrap();
+    assert!(emulator.is_sleeping());
+    display.refresh().unwrap();
+    assert_eq!(emulator.controller(0).refresh_count, 2);
+    assert_eq!(emulator.pixel(6, 1), Some(E6Color::Blue));
+    assert!(emulator.violations().is_empty());
+}
+
+#[test]
+fn dual_display_shares_the_drawing_of_the_single_driver() {
+    let single_profile = PanelProfile::SPECTRA6_7IN3.with_resolution(40, 8);
+    let mut image = Grid::<Vec<u8>, E6Color>::new(vec![0; 8], 5, 3);
+    image.update(|(x, y), _| [E6Color::Red, E6Color::Blue, E6Color::Green][(x + y) % 3]);
+    let sprite = Sprite::new(image.as_view());
+    let frame: Vec<u8> = (0..160)
+        .map(|index| [0x01, 0x23, 0x56][index % 3])
+        .collect();
+
+    let single_emulator = Emulator::new();
+    let mut single = profile_display(&single_emulator, single_profile);
+    let dual_emulator = Emulator::with_controllers(2);
+    let transport = FourWire::new(
+        DualSpi::new(
+            dual_emulator.controller_spi(0),
+            dual_emulator.controller_spi(1),
+        ),
+        dual_emulator.dc_pin(),
+    )
+    .with_max_chunk_len(4);
+    let mut dual = E6DualDisplay::with_transport(
+        DUAL_PROFILE,
+        transport,
+        dual_emulator.rst_pin(),
+        dual_emulator.busy_pin(),
+        dual_emulator.delay(),
+        Nibbles::new(
+            vec![0u8; underlying_data_len(DUAL_PROFILE.len())],
+            DUAL_PROFILE.len(),
+        ),
+    );
+    single.initialize().unwrap();
+    dual.initialize().unwrap();
+
+    single.update_packed(&frame).unwrap();
+    dual.update_packed(&frame).unwrap();
+    single.refresh().unwrap();
+    dual.refresh().unwrap();
+    assert_eq!(
+        panel_pixels(&dual_emulator, 40, 8),
+        panel_pixels(&single_emulator, 40, 8)
+    );
+
+    draw_with_every_method(&mut single);
+    draw_with_every_method(&mut dual);
+    single.blit(&sprite, Point::new(17, 2)).unwrap();
+    dual.blit(&sprite, Point::new(17, 2)).unwrap();
+    single.refresh().unwrap();
+    dual.refresh().unwrap();
+    assert_eq!(
+        panel_pixels(&dual_emulator, 40, 8),
+        panel_pixels(&single_emulator, 40, 8)
+    );
+    assert_eq!(dual_emulator.pixel(1, 1), Some(E6Color::Blue));
+    assert_eq!(dual_emulator.pixel(20, 2), Some(E6Color::Red));
+
+    assert!(dual_emulator.violations().is_empty());
+    let writes = dual_emulator.spi_transactions().into_iter().flatten();
+    assert!(writes.into_iter().all(|len| len <= 4));
+}
+
+#[test]
+fn dual_display_streams_both_halves() {
+    let emulator = Emulator::with_controllers(2);
+    let transport = FourWire::new(
+        DualSpi::new(emulator.controller_spi(0), emulator.controller_spi(1)),
+        emulator.dc_pin(),
+    );
+    let mut display = E6DualDisplay::without_frame_buffer(
+        DUAL_PROFILE,
+        transport,
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+    );
+    display.initialize().unwrap();
+
+    let color = |x: i32| match x {
+        0..=9 => E6Color::Red,
+        10..=29 => E6Color::Blue,
+        _ => E6Color::Green,
+    };
+    display.refresh_from_fn(|point| color(point.x)).unwrap();
+    for x in 0..40 {
+        assert_eq!(emulator.pixel(x, 7), Some(color(x as i32)), "{x}");
+    }
+
+    let mut row_buffer = [0u8; 20];
+    let mut rows = Vec::new();
+    display
+        .refresh_from_rows(&mut row_buffer, |y, row| {
+            rows.push(y);
+            row.fill(0..40, E6Color::Yellow);
+            row.set(39, E6Color::Black);
+        })
+        .unwrap();
+    assert_eq!(
+        rows,
+        [(0..8).collect::<Vec<_>>(), (0..8).collect()].concat()
+    );
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Yellow));
+    assert_eq!(emulator.pixel(39, 7), Some(E6Color::Black));
+
+    let mut band_buffer = [0u8; 60];
+    let mut bands = Vec::new();
+    display
+        .refresh_banded(&mut band_buffer, E6Color::White, |band| {
+            bands.push(band.rows());
+            Rectangle::new(Point::new(16, 2), Size::new(8, 4))
+                .into_styled(PrimitiveStyle::with_fill(E6Color::Red))
+                .draw(band)
+        })
+        .unwrap();
+    assert_eq!(bands, [0..3, 3..6, 6..8, 0..3, 3..6, 6..8]);
+    for (x, y) in [(15, 2), (24, 5), (39, 7)] {
+        assert_eq!(emulator.pixel(x, y), Some(E6Color::White), "({x}, {y})");
+    }
+    for (x, y) in [(16, 2), (19, 5), (20, 2), (23, 5)] {
+        assert_eq!(emulator.pixel(x, y), Some(E6Color::Red), "({x}, {y})");
+    }
+    assert_eq!(emulator.controller(0).refresh_count, 3);
+    assert_eq!(emulator.controller(1).refresh_count, 3);
+    assert!(emulator.violations().is_empty());
+}
+
+#[test]
+fn rotated_display_maps_drawing_and_partial_window() {
+    let emulator = Emulator::new();
+    let mut display = profile_display(
+        &emulator,
+        PanelProfile::SPECTRA6_7IN3.with_resolution(16, 8),
+    );
+    display.set_orientation(Orientation::new(Rotation::Deg90));
+    assert_eq!(display.size(), Size::new(8, 16));
+    assert_eq!((display.width(), display.height()), (8, 16));
+
+    display.initialize().unwrap();
+    display.clear(E6Color::White).unwrap();
+    display
+        .draw_iter([
+            Pixel(Point::new(0, 0), E6Color::Red),
+            Pixel(Point::new(7, 15), E6Color::Blue),
+            Pixel(Point::new(8, 0), E6Color::Green),
+            Pixel(Point::new(-1, 3), E6Color::Green),
+        ])
+        .unwrap();
+    display.refresh().unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(15, 0), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(0, 7), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::White));
+
+    display
+        .partial_update(iter::repeat_n(E6Color::Yellow, 8), 0..=1, 0..=3)
+        .unwrap();
+    display.partial_refresh(0..=1, 0..=3).unwrap();
+    assert_eq!(
+        emulator.window(),
+        Some(Window {
+            x_start: 12,
+            x_end: 15,
+            y_start: 0,
+            y_end: 1,
+        })
+    );
+    assert_eq!(emulator.pixel(12, 1), Some(E6Color::Yellow));
+    assert_eq!(emulator.pixel(11, 1), Some(E6Color::White));
+}
+
+fn panel_pixels(emulator: &Emulator, width: u16, height: u16) -> Vec<Option<E6Color>> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| emulator.pixel(x, y))
+        .collect()
+}
+
+#[test]
+fn fast_fills_test() {
+    let orientations = [
+        Orientation::new(Rotation::Deg0),
+        Orientation::new(Rotation::Deg90),
+        Orientation::new(Rotation::Deg180).with_mirror_horizontal(true),
+        Orientation::new(Rotation::Deg270).with_mirror_vertical(true),
+        Orientation::new(Rotation::Deg0).with_mirror_horizontal(true),
+    ];
+    let areas = [
+        Rectangle::new(Point::new(1, 1), Size::new(5, 3)),
+        Rectangle::new(Point::new(-2, 3), Size::new(7, 20)),
+        Rectangle::new(Point::new(9, -1), Size::new(10, 4)),
+        Rectangle::new(Point::new(3, 2), Size::new(0, 4)),
+    ];
+    let colors = [E6Color::Red, E6Color::Blue, E6Color::Yellow, E6Color::Green];
+    let profile = PanelProfile::SPECTRA6_7IN3.with_resolution(14, 10);
+    for orientation in orientations {
+        let fast_emulator = Emulator::new();
+        let mut fast = profile_display(&fast_emulator, profile);
+        let slow_emulator = Emulator::new();
+        let mut slow = profile_display(&slow_emulator, profile);
+        for display in [&mut fast, &mut slow] {
+            display.set_orientation(orientation);
+            display.initialize().unwrap();
+        }
+
+        fast.clear(E6Color::White).unwrap();
+        slow.draw_iter(
+            slow.bounding_box()
+                .points()
+                .map(|point| Pixel(point, E6Color::White)),
+        )
+        .unwrap();
+        for (area, color) in areas.iter().zip(colors) {
+            fast.fill_solid(area, color).unwrap();
+            slow.draw_iter(area.points().map(|point| Pixel(point, color)))
+                .unwrap();
+        }
+        let pattern = || {
+            (0u8..).map(|index| [E6Color::Black, E6Color::Green, E6Color::Red][index as usize % 3])
+        };
+        for area in &areas {
+            fast.fill_contiguous(area, pattern()).unwrap();
+            slow.draw_iter(
+                area.points()
+                    .zip(pattern())
+                    .map(|(point, color)| Pixel(point, color)),
+            )
+            .unwrap();
+        }
+        fast.refresh().unwrap();
+        slow.refresh().unwrap();
+
+        assert_eq!(
+            panel_pixels(&fast_emulator, 14, 10),
+            panel_pixels(&slow_emulator, 14, 10),
+            "{orientation:?}"
+        );
+    }
+}
+
+#[test]
+fn blits_match_pixel_drawing() {
+    let orientations = [
+        Orientation::new(Rotation::Deg0),
+        Orientation::new(Rotation::Deg90),
+        Orientation::new(Rotation::Deg180).with_mirror_horizontal(true),
+        Orientation::new(Rotation::Deg270).with_mirror_vertical(true),
+    ];
+    let mut image = Grid::<Vec<u8>, E6Color>::new(vec![0; 8], 5, 3);
+    image.update(|(x, y), _| {
+        [E6Color::White, E6Color::Red, E6Color::Blue, E6Color::Green][(x + y) % 4]
+    });
+    let mut mask = Grid::<Vec<u8>, u8, 1>::new(vec![0; 2], 5, 3);
+    mask.update(|(x, y), _| (x != y) as u8);
+    let opaque = Sprite::new(image.as_view());
+    let keyed = Sprite::new(image.as_view()).with_color_key(E6Color::White);
+    let masked = Sprite::new(image.as_view()).with_mask(mask.as_view());
+    let positions = [Point::new(1, 1), Point::new(-2, 8), Point::new(11, -1)];
+    let profile = PanelProfile::SPECTRA6_7IN3.with_resolution(14, 10);
+    for orientation in orientations {
+        let fast_emulator = Emulator::new();
+        let mut fast = profile_display(&fast_emulator, profile);
+        let slow_emulator = Emulator::new();
+        let mut slow = profile_display(&slow_emulator, profile);
+        for display in [&mut fast, &mut slow] {
+            display.set_orientation(orientation);
+            display.initialize().unwrap();
+            display.clear(E6Color::Yellow).unwrap();
+        }
+
+        for position in positions {
+            fast.blit(&opaque, position).unwrap();
+            fast.blit(&keyed, position + Point::new(2, 1)).unwrap();
+            fast.blit(&masked, position + Point::new(1, 2)).unwrap();
+            let pixels = |offset: Point, visible: fn(usize, usize, E6Color) -> bool| {
+                image
+                    .iter()
+                    .filter(move |((x, y), color)| visible(*x, *y, *color))
+                    .map(move |((x, y), color)| {
+                        Pixel(position + offset + Point::new(x as i32, y as i32), color)
+                    })
+            };
+            slow.draw_iter(pixels(Point::zero(), |_, _, _| true))
+                .unwrap();
+            slow.draw_iter(pixels(Point::new(2, 1), |_, _, color| {
+                color != E6Color::White
+            }))
+            .unwrap();
+            slow.draw_iter(pixels(Point::new(1, 2), |x, y, _| x != y))
+                .unwrap();
+        }
+        fast.refresh().unwrap();
+        slow.refresh().unwrap();
+
+        assert_eq!(
+            panel_pixels(&fast_emulator, 14, 10),
+            panel_pixels(&slow_emulator, 14, 10),
+            "{orientation:?}"
+        );
+    }
+}
+
+#[test]
+fn blit_needs_whole_frame_buffer() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    block_on(display.initialize()).unwrap();
+    let image = Grid::<[u8; 2], E6Color>::new([0x33; 2], 2, 2);
+    display
+        .blit(&Sprite::new(image.as_view()), Point::new(3, 4))
+        .unwrap();
+    block_on(display.refresh()).unwrap();
+    assert_eq!(emulator.pixel(3, 4), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(4, 5), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(5, 5), Some(E6Color::Black));
+
+    let mut streaming = streaming_display(&Emulator::new());
+    assert!(matches!(
+        streaming.blit(&Sprite::new(image.as_view()), Point::zero()),
+        Err(Error::BufferTooSmall { .. })
+    ));
+}
+
+#[test]
+fn packed_updates_copy_native_rows() {
+    let emulator = Emulator::new();
+    let profile = PanelProfile::SPECTRA6_7IN3.with_resolution(8, 4);
+    let mut display = profile_display(&emulator, profile);
+    // The orientation doesn't apply to packed frames.
+    display.set_orientation(Orientation::new(Rotation::Deg90));
+    display.initialize().unwrap();
+
+    assert_eq!(
+        display.update_packed(&[0x11; 15]),
+        Err(Error::BufferTooSmall {
+            required: 16,
+            len: 15
+        })
+    );
+    let frame: Vec<u8> = (0..16)
+        .map(|index| [0x01, 0x23, 0x56, 0x10][index % 4])
+        .collect();
+    display.update_packed(&frame).unwrap();
+    // Odd start column and width, every row starts on a byte boundary.
+    display
+        .partial_update_packed(&[0x33, 0x30, 0x55, 0x50], 3..=5, 1..=2)
+        .unwrap();
+    assert_eq!(
+        display.partial_update_packed(&[0; 4], 6..=8, 0..=0),
+        Err(Error::OutOfBounds { index: 8, len: 8 })
+    );
+    display.refresh().unwrap();
+
+    let row = [
+        E6Color::Black,
+        E6Color::White,
+        E6Color::Yellow,
+        E6Color::Red,
+        E6Color::Blue,
+        E6Color::Green,
+        E6Color::White,
+        E6Color::Black,
+    ];
+    for y in 0..4 {
+        for x in 0..8 {
+            let expected = match (x, y) {
+                (3..=5, 1) => E6Color::Red,
+                (3..=5, 2) => E6Color::Blue,
+                _ => row[x as usize],
+            };
+            assert_eq!(emulator.pixel(x, y), Some(expected), "({x}, {y})");
+        }
+    }
+}
+
+#[test]
+fn async_packed_update_refreshes_frame() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    block_on(display.initialize()).unwrap();
+    let frame = vec![0x55; WIDTH as usize * HEIGHT as usize / 2];
+    display.update_packed(&frame).unwrap();
+    display
+        .partial_update_packed(&[0x33; 2], 11..=14, 7..=7)
+        .unwrap();
+    block_on(display.refresh()).unwrap();
+
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Blue));
+    assert_eq!(emulator.pixel(11, 7), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(14, 7), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(15, 7), Some(E6Color::Blue));
+}
+
+#[test]
+fn async_mirrored_display_maps_drawing() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    display.set_orientation(Orientation::new(Rotation::Deg180).with_mirror_horizontal(true));
+    assert_eq!(display.size(), Size::new(WIDTH as u32, HEIGHT as u32));
+
+    block_on(async {
+        display.initialize().await.unwrap();
+        display.clear(E6Color::White).unwrap();
+        Pixel(Point::new(2, 0), E6Color::Red)
+            .draw(&mut display)
+            .unwrap();
+        display.refresh().await.unwrap();
+    });
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(2, HEIGHT - 1), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(2, 0), Some(E6Color::White));
+}
+
+#[test]
+fn blocking_refresh_times_out_when_busy_is_stuck() {
+    let emulator = Emulator::new().with_timings(EmulatorTimings {
+        refresh_ms: 60_000,
+        ..EmulatorTimings::default()
+    });
+    let profile = PanelProfile::SPECTRA6_7IN3
+        .with_resolution(WIDTH, HEIGHT)
+        .with_busy_policy(BusyPolicy::new(100, 20_000).with_refresh_timeout_ms(30_000));
+    let mut display = profile_display(&emulator, profile);
+    display.initialize().unwrap();
+    let start_ms = emulator.elapsed_ms();
+
+    let result = display.refresh();
+
+    assert!(matches!(
+        result,
+        Err(Error::BusyTimeout(BusyPhase::Refresh))
+    ));
+    let waited_ms = emulator.elapsed_ms() - start_ms;
+    assert!((30_000..31_000).contains(&waited_ms), "{waited_ms}");
+}
+
+#[test]
+fn async_refresh_times_out_when_busy_is_stuck() {
+    let emulator = Emulator::new().with_timings(EmulatorTimings {
+        power_off_ms: 5_000,
+        ..EmulatorTimings::default()
+    });
+    let mut display = async_display(&emulator);
+    block_on(async {
+        display.initialize().await.unwrap();
+        display.refresh().await.unwrap();
+    });
+    assert_eq!(emulator.refresh_count(), 1);
+
+    let profile = PanelProfile::SPECTRA6_7IN3
+        .with_resolution(WIDTH, HEIGHT)
+        .with_busy_policy(BusyPolicy::default().with_power_off_timeout_ms(1_000));
+    let mut display = AsyncE6Display::with_profile(
+        profile,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+    let result = block_on(async {
+        display.initialize().await.unwrap();
+        display.refresh().await
+    });
+    assert!(matches!(
+        result,
+        Err(Error::BusyTimeout(BusyPhase::PowerOff))
+    ));
+    assert!(emulator.violations().is_empty());
+}
+
+#[test]
+fn busy_hook_runs_while_panel_is_busy() {
+    let emulator = Emulator::new();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let hook_calls = calls.clone();
+    let mut display = blocking_display(&emulator).with_busy_hook(move |phase, waited_ms| {
+        hook_calls.lock().unwrap().push((phase, waited_ms))
+    });
+    display.initialize().unwrap();
+    display.refresh().unwrap();
+
+    let calls = calls.lock().unwrap();
+    let refresh_calls: Vec<u32> = calls
+        .iter()
+        .filter(|(phase, _)| *phase == BusyPhase::Refresh)
+        .map(|(_, waited_ms)| *waited_ms)
+        .collect();
+    assert_eq!(refresh_calls.len(), 120);
+    assert_eq!(refresh_calls[..3], [0, 100, 200]);
+    assert!(calls.iter().any(|(phase, _)| *phase == BusyPhase::PowerOff));
+    assert!(
+        !calls
+            .iter()
+            .any(|(phase, _)| *phase == BusyPhase::DataTransfer)
+    );
+}
+
+#[test]
+fn async_busy_hook_runs_while_panel_is_busy() {
+    let emulator = Emulator::new();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let hook_calls = calls.clone();
+    let mut display = async_display(&emulator).with_busy_hook(move |phase, waited_ms| {
+        hook_calls.lock().unwrap().push((phase, waited_ms))
+    });
+    block_on(async {
+        display.initialize().await.unwrap();
+        display.refresh().await.unwrap();
+    });
+
+    let calls = calls.lock().unwrap();
+    let refresh_calls: Vec<u32> = calls
+        .iter()
+        .filter(|(phase, _)| *phase == BusyPhase::Refresh)
+        .map(|(_, waited_ms)| *waited_ms)
+        .collect();
+    assert_eq!(refresh_calls.len(), 120);
+    assert_eq!(refresh_calls[..3], [0, 100, 200]);
+    assert!(
+        !calls
+            .iter()
+            .any(|(phase, _)| *phase == BusyPhase::DataTransfer)
+    );
+    assert!(emulator.violations().is_empty());
+}
+
+#[test]
+fn async_driver_accepts_hooks_that_are_not_send() {
+    let emulator = Emulator::new();
+    let calls = Rc::new(Cell::new(0));
+    let hook_calls = calls.clone();
+    let mut display =
+        async_display(&emulator).with_busy_hook(move |_, _| hook_calls.set(hook_calls.get() + 1));
+    block_on(async {
+        AsyncDisplay::initialize(&mut display).await.unwrap();
+        AsyncDisplay::refresh(&mut display).await.unwrap();
+    });
+    assert!(calls.get() >= 120);
+}
+
+#[test]
+fn zero_poll_interval_still_times_out() {
+    let policy = BusyPolicy::new(0, 1_000);
+    assert_eq!(policy.poll_interval_ms, 1);
+    let policy = BusyPolicy {
+        poll_interval_ms: 0,
+        ..policy
+    };
+    let timings = EmulatorTimings {
+        refresh_ms: 60_000,
+        ..EmulatorTimings::default()
+    };
+    let profile = PanelProfile::SPECTRA6_7IN3
+        .with_resolution(WIDTH, HEIGHT)
+        .with_busy_policy(policy);
+
+    let emulator = Emulator::new().with_timings(timings);
+    let mut display = profile_display(&emulator, profile);
+    display.initialize().unwrap();
+    assert!(matches!(
+        display.refresh(),
+        Err(Error::BusyTimeout(BusyPhase::Refresh))
+    ));
+
+    let emulator = Emulator::new().with_timings(timings);
+    let mut display = AsyncE6Display::with_profile(
+        profile,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+    let result = block_on(async {
+        display.initialize().await.unwrap();
+        display.refresh().await
+    });
+    assert!(matches!(
+        result,
+        Err(Error::BusyTimeout(BusyPhase::Refresh))
+    ));
+}
+
+#[test]
+fn busy_released_at_the_deadline_succeeds_in_both_drivers() {
+    let timings = EmulatorTimings {
+        refresh_ms: 1_000,
+        ..EmulatorTimings::default()
+    };
+    let profile = PanelProfile::SPECTRA6_7IN3
+        .with_resolution(WIDTH, HEIGHT)
+        .with_busy_policy(BusyPolicy::new(100, 30_000).with_refresh_timeout_ms(1_000));
+
+    let emulator = Emulator::new().with_timings(timings);
+    let mut display = profile_display(&emulator, profile);
+    display.initialize().unwrap();
+    display.refresh().unwrap();
+
+    let emulator = Emulator::new().with_timings(timings);
+    let mut display = AsyncE6Display::with_profile(
+        profile,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+    block_on(async {
+        display.initialize().await.unwrap();
+        display.refresh().await.unwrap();
+    });
+}
+
+struct FailingSpi;
+
+impl embedded_hal::spi::ErrorType for FailingSpi {
+    type Error = embedded_hal::spi::ErrorKind;
+}
+
+impl embedded_hal::spi::SpiDevice for FailingSpi {
+    fn transaction(
+        &mut self,
+        _operations: &mut [embedded_hal::spi::Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        Err(embedded_hal::spi::ErrorKind::ModeFault)
+    }
+}
+
+#[test]
+fn spi_error_records_failed_command() {
+    let emulator = Emulator::new();
+    let mut display = E6Display::new(
+        WIDTH,
+        HEIGHT,
+        FailingSpi,
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+
+    let error = display.initialize().unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::SpiError {
+            kind: embedded_hal::spi::ErrorKind::ModeFault,
+            command: Some(CommandCode::INIT),
+            ..
+        }
+    ));
+    let error: &dyn core::error::Error = &error;
+    assert!(error.to_string().contains("INIT"));
+    assert_eq!(display.power_state(), PowerState::Uninitialized);
+    assert_eq!(display.refresh().unwrap_err(), Error::NotInitialized);
+}
+
+#[test]
+fn drivers_reject_bad_configuration_and_uninitialized_use() {
+    let emulator = Emulator::new();
+    let result = TestDisplay::try_with_profile(
+        PanelProfile::SPECTRA6_13IN3,
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+    assert!(matches!(
+        result,
+        Err(Error::BufferTooSmall {
+            required: 1_920_000,
+            len: 384_000
+        })
+    ));
+
+    let mut display = async_display(&emulator);
+    block_on(async {
+        assert_eq!(display.refresh().await.unwrap_err(), Error::NotInitialized);
+        assert_eq!(
+            display.partial_refresh(0..=1, 0..=1).await.unwrap_err(),
+            Error::NotInitialized
+        );
+    });
+    assert!(emulator.commands().is_empty());
+}
+
+#[test]
+fn blocking_and_async_drivers_send_the_same_commands() {
+    let blocking_emulator = Emulator::new();
+    let mut display = blocking_display(&blocking_emulator);
+    display.initialize().unwrap();
+    display
+        .partial_update(iter::repeat(E6Color::Green), 100..=139, 20..=29)
+        .unwrap();
+    display.partial_refresh(100..=139, 20..=29).unwrap();
+    display.refresh().unwrap();
+    display.sleep().unwrap();
+    display.refresh().unwrap();
+
+    let async_emulator = Emulator::new();
+    let mut display = async_display(&async_emulator);
+    block_on(async {
+        display.initialize().await.unwrap();
+        display
+            .partial_update(iter::repeat(E6Color::Green), 100..=139, 20..=29)
+            .await
+            .unwrap();
+        display.partial_refresh(100..=139, 20..=29).await.unwrap();
+        display.refresh().await.unwrap();
+        display.sleep().await.unwrap();
+        display.refresh().await.unwrap();
+    });
+
+    assert_eq!(blocking_emulator.commands(), async_emulator.commands());
+    assert!(blocking_emulator.violations().is_empty());
+    assert!(async_emulator.violations().is_empty());
+}
+
+#[test]
+fn three_wire_transport_sends_nine_bit_words() {
+    let four_wire = Emulator::new();
+    let mut display = blocking_display(&four_wire);
+    display.initialize().unwrap();
+    display
+        .partial_update(iter::repeat(E6Color::Blue), 30..=59, 40..=49)
+        .unwrap();
+    display.partial_refresh(30..=59, 40..=49).unwrap();
+    display.refresh().unwrap();
+
+    let three_wire = Emulator::new();
+    let mut display = E6Display::with_transport(
+        PanelProfile::SPECTRA6_7IN3,
+        ThreeWire::new(three_wire.three_wire_spi()),
+        three_wire.rst_pin(),
+        three_wire.busy_pin(),
+        three_wire.delay(),
+        frame_buffer(),
+    );
+    display.initialize().unwrap();
+    display
+        .partial_update(iter::repeat(E6Color::Blue), 30..=59, 40..=49)
+        .unwrap();
+    display.partial_refresh(30..=59, 40..=49).unwrap();
+    display.refresh().unwrap();
+
+    assert_eq!(three_wire.commands(), four_wire.commands());
+    assert!(three_wire.violations().is_empty());
+    assert_eq!(three_wire.pixel(30, 40), Some(E6Color::Blue));
+    assert_eq!(three_wire.pixel(29, 40), Some(E6Color::Black));
+}
+
+#[test]
+fn async_three_wire_transport_refreshes_window() {
+    let emulator = Emulator::new();
+    let mut display = AsyncE6Display::with_transport(
+        PanelProfile::SPECTRA6_7IN3,
+        ThreeWire::new(emulator.three_wire_spi()),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+    block_on(async {
+        display.initialize().await.unwrap();
+        display
+            .partial_update(iter::repeat(E6Color::Yellow), 0..=9, 0..=9)
+            .await
+            .unwrap();
+        display.partial_refresh(0..=9, 0..=9).await.unwrap();
+    });
+
+    assert_eq!(emulator.resolution(), (WIDTH, HEIGHT));
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(9, 9), Some(E6Color::Yellow));
+    assert_eq!(emulator.pixel(10, 9), Some(E6Color::Black));
+}
+
+/// Transport with the DC line behind an expander that is not an `OutputPin`.
+struct ExpanderTransport {
+    spi: EmulatorSpi,
+    dc_pin: EmulatorDcPin,
+}
+
+impl ExpanderTransport {
+    fn select(&mut self, data: bool) {
+        if data {
+            self.dc_pin.set_high().unwrap();
+        } else {
+            self.dc_pin.set_low().unwrap();
+        }
+    }
+}
+
+impl Transport for ExpanderTransport {
+    fn write_command(&mut self, command: CommandCode) -> Result<(), Error> {
+        self.select(false);
+        self.spi.write(&[command as u8]).unwrap();
+        Ok(())
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.select(true);
+        self.spi.write(data).unwrap();
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_transport_drives_the_controller() {
+    let emulator = Emulator::new();
+    let transport = ExpanderTransport {
+        spi: emulator.spi(),
+        dc_pin: emulator.dc_pin(),
+    };
+    let mut display = E6Display::with_transport(
+        PanelProfile::SPECTRA6_7IN3,
+        transport,
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    );
+    display.initialize().unwrap();
+    display.clear(E6Color::Red).unwrap();
+    display.refresh().unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(WIDTH - 1, HEIGHT - 1), Some(E6Color::Red));
+}
+
+fn streaming_display(
+    emulator: &Emulator,
+) -> E6Display<
+    FourWire<EmulatorSpi, EmulatorDcPin>,
+    EmulatorResetPin,
+    EmulatorBusyPin,
+    EmulatorDelay,
+    [u8; 0],
+> {
+    E6Display::without_frame_buffer(
+        PanelProfile::SPECTRA6_7IN3,
+        FourWire::new(emulator.spi(), emulator.dc_pin()),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+    )
+}
+
+#[test]
+fn streaming_refresh_from_fn_uses_logical_coordinates() {
+    let emulator = Emulator::new();
+    let mut display = streaming_display(&emulator);
+    display.set_orientation(Orientation::new(Rotation::Deg90));
+    display.initialize().unwrap();
+    emulator.clear_commands();
+
+    let mut calls = 0;
+    display
+        .refresh_from_fn(|point| {
+            calls += 1;
+            if point.x < 10 && point.y < 20 {
+                E6Color::Red
+            } else {
+                E6Color::White
+            }
+        })
+        .unwrap();
+
+    assert_eq!(calls, WIDTH as usize * HEIGHT as usize);
+    assert_commands(&emulator, &REFRESH_COMMANDS);
+    assert!(emulator.violations().is_empty());
+    // Logical (x, y) is shown at panel (WIDTH - 1 - y, x).
+    assert_eq!(emulator.pixel(WIDTH - 1, 0), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(WIDTH - 20, 9), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(WIDTH - 21, 9), Some(E6Color::White));
+    assert_eq!(emulator.pixel(WIDTH - 1, 10), Some(E6Color::White));
+}
+
+#[test]
+fn streaming_refresh_from_rows_sends_every_row() {
+    let emulator = Emulator::new();
+    let mut display = streaming_display(&emulator);
+    display.initialize().unwrap();
+
+    let mut row_buffer = [0u8; WIDTH as usize / 2];
+    display
+        .refresh_from_rows(&mut row_buffer, |y, row| {
+            for x in 0..row.len() {
+                row.set(
+                    x,
+                    if x == y as usize {
+                        E6Color::Green
+                    } else {
+                        E6Color::Black
+                    },
+                );
+            }
+        })
+        .unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Green));
+    assert_eq!(emulator.pixel(479, 479), Some(E6Color::Green));
+    assert_eq!(emulator.pixel(480, 479), Some(E6Color::Black));
+
+    let mut short_buffer = [0u8; 16];
+    assert_eq!(
+        display.refresh_from_rows(&mut short_buffer, |_, _| {}),
+        Err(Error::BufferTooSmall {
+            required: WIDTH as usize,
+            len: 32,
+        })
+    );
+}
+
+#[test]
+fn streaming_display_ignores_drawing_and_buffered_refresh() {
+    let emulator = Emulator::new();
+    let mut display = streaming_display(&emulator);
+    display.initialize().unwrap();
+    emulator.clear_commands();
+
+    display.clear(E6Color::Red).unwrap();
+    assert_eq!(
+        display.refresh(),
+        Err(Error::BufferTooSmall {
+            required: WIDTH as usize * HEIGHT as usize,
+            len: 0,
+        })
+    );
+    assert!(emulator.commands().is_empty());
+}
+
+#[test]
+fn async_streaming_refresh_from_fn() {
+    let emulator = Emulator::new();
+    let mut display = AsyncE6Display::without_frame_buffer(
+        PanelProfile::SPECTRA6_7IN3,
+        FourWire::new(emulator.spi(), emulator.dc_pin()),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+    );
+    block_on(async {
+        display.initialize().await.unwrap();
+        display
+            .refresh_from_fn(|point| {
+                if point.y % 2 == 0 {
+                    E6Color::Yellow
+                } else {
+                    E6Color::Blue
+                }
+            })
+            .await
+            .unwrap();
+    });
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.refresh_count(), 1);
+    assert_eq!(emulator.pixel(799, 0), Some(E6Color::Yellow));
+    assert_eq!(emulator.pixel(0, 479), Some(E6Color::Blue));
+}
+
+fn draw_scene<D: DrawTarget<Color = E6Color>>(target: &mut D) -> Result<(), D::Error> {
+    Rectangle::new(Point::new(5, 30), Size::new(200, 100))
+        .into_styled(PrimitiveStyle::with_fill(E6Color::Red))
+        .draw(target)?;
+    Rectangle::new(Point::new(300, 10), Size::new(20, 400))
+        .into_styled(PrimitiveStyle::with_stroke(E6Color::Blue, 3))
+        .draw(target)
+}
+
+#[test]
+fn banded_refresh_matches_frame_buffer_rendering() {
+    for orientation in [
+        Orientation::default(),
+        Orientation::new(Rotation::Deg270).with_mirror_horizontal(true),
+    ] {
+        let buffered = Emulator::new();
+        let mut display = blocking_display(&buffered);
+        display.set_orientation(orientation);
+        display.initialize().unwrap();
+        display.clear(E6Color::White).unwrap();
+        draw_scene(&mut display).unwrap();
+        display.refresh().unwrap();
+
+        let banded = Emulator::new();
+        let mut display = streaming_display(&banded);
+        display.set_orientation(orientation);
+        display.initialize().unwrap();
+        let mut band_buffer = vec![0u8; 16 * 1024];
+        let mut bands = Vec::new();
+        display
+            .refresh_banded(&mut band_buffer, E6Color::White, |band| {
+                bands.push(band.rows());
+                draw_scene(band)
+            })
+            .unwrap();
+
+        assert_eq!(
+            bands,
+            [
+                0..40,
+                40..80,
+                80..120,
+                120..160,
+                160..200,
+                200..240,
+                240..280,
+                280..320,
+                320..360,
+                360..400,
+                400..440,
+                440..480
+            ]
+        );
+        assert!(banded.violations().is_empty());
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                assert_eq!(banded.pixel(x, y), buffered.pixel(x, y), "({x}, {y})");
+            }
+        }
+    }
+}
+
+#[test]
+fn banded_refresh_needs_a_whole_row() {
+    let emulator = Emulator::new();
+    let mut display = streaming_display(&emulator);
+    display.initialize().unwrap();
+    emulator.clear_commands();
+
+    let mut band_buffer = [0u8; 100];
+    assert_eq!(
+        display.refresh_banded(&mut band_buffer, E6Color::White, |_| Ok(())),
+        Err(Error::BufferTooSmall {
+            required: WIDTH as usize,
+            len: 200,
+        })
+    );
+    assert!(emulator.commands().is_empty());
+}
+
+#[test]
+fn async_banded_refresh_draws_every_band() {
+    let emulator = Emulator::new();
+    let mut display = async_display(&emulator);
+    let mut band_buffer = vec![0u8; WIDTH as usize * 100 / 2];
+    let mut calls = 0;
+    block_on(async {
+        display.initialize().await.unwrap();
+        display
+            .refresh_banded(&mut band_buffer, E6Color::Yellow, |band| {
+                calls += 1;
+                draw_scene(band)
+            })
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(calls, 5);
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Yellow));
+    assert_eq!(emulator.pixel(100, 99), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(100, 100), Some(E6Color::Red));
+    assert_eq!(emulator.pixel(300, 479), Some(E6Color::Yellow));
+}
+
+fn frame_transactions(emulator: &Emulator) -> Vec<Vec<usize>> {
+    emulator
+        .spi_transactions()
+        .into_iter()
+        .filter(|writes| writes.iter().sum::<usize>() > 1_000)
+        .collect()
+}
+
+fn chunked_display(
+    emulator: &Emulator,
+    transport: FourWire<EmulatorSpi, EmulatorDcPin>,
+) -> TestDisplay {
+    E6Display::with_transport(
+        PanelProfile::SPECTRA6_7IN3,
+        transport,
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        frame_buffer(),
+    )
+}
+
+#[test]
+fn chunked_frame_upload_batches_chunks_into_transactions() {
+    let emulator = Emulator::new();
+    let transport = FourWire::new(emulator.spi(), emulator.dc_pin()).with_max_chunk_len(4096);
+    let mut display = chunked_display(&emulator, transport);
+    display.initialize().unwrap();
+    display.clear(E6Color::Green).unwrap();
+    emulator.clear_commands();
+    display.refresh().unwrap();
+
+    let mut last = vec![4096; 6];
+    last.push(3584);
+    let mut expected = vec![vec![4096; 8]; 5];
+    expected.push(last);
+    assert_eq!(frame_transactions(&emulator), expected);
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(WIDTH - 1, HEIGHT - 1), Some(E6Color::Green));
+}
+
+#[test]
+fn chunked_frame_upload_sends_any_number_of_chunks() {
+    let emulator = Emulator::new();
+    let transport = FourWire::new(emulator.spi(), emulator.dc_pin()).with_max_chunk_len(1024);
+    let mut display = chunked_display(&emulator, transport);
+    display.initialize().unwrap();
+    display.clear(E6Color::Blue).unwrap();
+    display.refresh().unwrap();
+    let transactions = frame_transactions(&emulator);
+    assert_eq!(transactions.len(), 24);
+    assert!(transactions.iter().all(|writes| writes.len() <= 8));
+    assert_eq!(transactions.concat().iter().sum::<usize>(), 192_000);
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(WIDTH - 1, HEIGHT - 1), Some(E6Color::Blue));
+
+    let emulator = Emulator::new();
+    let transport = FourWire::new(emulator.spi(), emulator.dc_pin())
+        .with_max_chunk_len(1024)
+        .with_chunks_per_transaction(100);
+    let mut display = chunked_display(&emulator, transport);
+    display.initialize().unwrap();
+    display.refresh().unwrap();
+    let mut last = vec![1024; 59];
+    last.push(512);
+    assert_eq!(
+        frame_transactions(&emulator),
+        [vec![1024; 64], vec![1024; 64], last]
+    );
+}
+
+/// Polls the future to completion and counts how often it was pending.
+fn count_pending<F: Future>(future: F) -> usize {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    let mut pending = 0;
+    while future.as_mut().poll(&mut context).is_pending() {
+        pending += 1;
+    }
+    pending
+}
+
+#[test]
+fn async_chunked_frame_upload_yields_between_transactions() {
+    let refresh_pending = |transport: FourWire<EmulatorSpi, EmulatorDcPin>, emulator: &Emulator| {
+        let mut display = AsyncE6Display::with_transport(
+            PanelProfile::SPECTRA6_7IN3,
+            transport,
+            emulator.rst_pin(),
+            emulator.busy_pin(),
+            emulator.delay(),
+            frame_buffer(),
+        );
+        block_on(display.initialize()).unwrap();
+        emulator.clear_commands();
+        count_pending(async { display.refresh().await.unwrap() })
+    };
+
+    let emulator = Emulator::new();
+    let whole = refresh_pending(FourWire::new(emulator.spi(), emulator.dc_pin()), &emulator);
+    assert_eq!(frame_transactions(&emulator), [vec![192_000]]);
+
+    let emulator = Emulator::new();
+    let batched = refresh_pending(
+        FourWire::new(emulator.spi(), emulator.dc_pin()).with_max_chunk_len(1024),
+        &emulator,
+    );
+    assert_eq!(frame_transactions(&emulator).len(), 24);
+    assert_eq!(batched, whole + 23);
+
+    let emulator = Emulator::new();
+    let chunked = refresh_pending(
+        FourWire::new(emulator.spi(), emulator.dc_pin())
+            .with_max_chunk_len(8192)
+            .with_chunks_per_transaction(1),
+        &emulator,
+    );
+    let transactions = frame_transactions(&emulator);
+    assert_eq!(transactions.len(), 24);
+    assert!(transactions.iter().all(|writes| writes.len() == 1));
+    assert_eq!(chunked, whole + 23);
+    assert!(emulator.violations().is_empty());
+}
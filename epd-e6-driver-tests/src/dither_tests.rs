@@ -0,0 +1,147 @@
+use embedded_graphics::pixelcolor::Rgb888;
+use epd_e6_driver::display::Error;
+use epd_e6_driver::dither::{ColorError, DitherMethod, Ditherer};
+use epd_e6_driver::prelude::*;
+use epd_e6_driver::testing::Emulator;
+use std::iter;
+
+const METHODS: [DitherMethod; 5] = [
+    DitherMethod::FloydSteinberg,
+    DitherMethod::Atkinson,
+    DitherMethod::SierraLite,
+    DitherMethod::Bayer,
+    DitherMethod::BlueNoise,
+];
+
+fn dither_image(
+    method: DitherMethod,
+    width: usize,
+    pixels: impl IntoIterator<Item = Rgb888>,
+) -> Vec<E6Color> {
+    let mut errors = vec![[0; 3]; method.error_buffer_len(width)];
+    Ditherer::new(method, width, &mut errors)
+        .unwrap()
+        .with_palette(Palette::IDEAL)
+        .dither(pixels)
+        .collect()
+}
+
+#[test]
+fn dither_palette_colors_test() {
+    let palette = [
+        E6Color::Black,
+        E6Color::White,
+        E6Color::Yellow,
+        E6Color::Red,
+        E6Color::Blue,
+        E6Color::Green,
+    ];
+    for method in METHODS {
+        for color in palette {
+            let dithered = dither_image(
+                method,
+                32,
+                iter::repeat_n(Palette::IDEAL.to_rgb888(color), 32 * 32),
+            );
+            assert!(
+                dithered.iter().all(|pixel| *pixel == color),
+                "{method:?} {color:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn dither_mid_grey_test() {
+    let grey = Rgb888::new(128, 128, 128);
+    for method in METHODS {
+        let dithered = dither_image(method, 64, iter::repeat_n(grey, 64 * 64));
+        let white = dithered
+            .iter()
+            .filter(|pixel| **pixel == E6Color::White)
+            .count();
+        let black = dithered
+            .iter()
+            .filter(|pixel| **pixel == E6Color::Black)
+            .count();
+        assert_eq!(white + black, dithered.len(), "{method:?}");
+        assert!(
+            (1843..=2253).contains(&white),
+            "{method:?}: {white} white pixels"
+        );
+    }
+}
+
+#[test]
+fn ordered_dither_thresholds_test() {
+    for (method, tile) in [(DitherMethod::Bayer, 8), (DitherMethod::BlueNoise, 16)] {
+        let levels = [64u8, 128, 192];
+        for level in levels {
+            let grey = Rgb888::new(level, level, level);
+            let dithered = dither_image(method, tile, iter::repeat_n(grey, tile * tile));
+            let white = dithered
+                .iter()
+                .filter(|pixel| **pixel == E6Color::White)
+                .count();
+            let expected = tile * tile * level as usize / 255;
+            assert!(white.abs_diff(expected) <= 1, "{method:?} {level}: {white}");
+        }
+    }
+}
+
+#[test]
+fn ditherer_error_buffer_test() {
+    let mut errors: Vec<ColorError> = vec![[0; 3]; 100];
+    assert_eq!(
+        Ditherer::new(DitherMethod::Atkinson, 800, &mut errors).err(),
+        Some(Error::BufferTooSmall {
+            required: 2400,
+            len: 100,
+        })
+    );
+    assert!(Ditherer::new(DitherMethod::BlueNoise, 800, &mut []).is_ok());
+}
+
+#[test]
+fn ditherer_zero_width_test() {
+    let mut errors: Vec<ColorError> = vec![[0; 3]; 100];
+    for method in [DitherMethod::FloydSteinberg, DitherMethod::Bayer] {
+        assert_eq!(
+            Ditherer::new(method, 0, &mut errors).err(),
+            Some(Error::ZeroWidth)
+        );
+    }
+}
+
+#[test]
+fn dither_gradient_update_test() {
+    const WIDTH: u16 = 64;
+    const HEIGHT: u16 = 32;
+    let emulator = Emulator::new();
+    let len = WIDTH as usize * HEIGHT as usize;
+    let mut display = E6Display::with_profile(
+        PanelProfile::SPECTRA6_7IN3.with_resolution(WIDTH, HEIGHT),
+        emulator.spi(),
+        emulator.dc_pin(),
+        emulator.rst_pin(),
+        emulator.busy_pin(),
+        emulator.delay(),
+        Nibbles::new(vec![0u8; underlying_data_len(len)], len),
+    );
+    display.initialize().unwrap();
+
+    let gradient = (0..HEIGHT).flat_map(|_| {
+        (0..WIDTH).map(|x| {
+            let level = (x as usize * 255 / (WIDTH as usize - 1)) as u8;
+            Rgb888::new(level, level / 2, 0)
+        })
+    });
+    let mut errors = vec![[0; 3]; DitherMethod::SierraLite.error_buffer_len(WIDTH as usize)];
+    let ditherer = Ditherer::new(DitherMethod::SierraLite, WIDTH as usize, &mut errors).unwrap();
+    display.update(ditherer.dither(gradient)).unwrap();
+    display.refresh().unwrap();
+
+    assert!(emulator.violations().is_empty());
+    assert_eq!(emulator.pixel(0, 0), Some(E6Color::Black));
+    assert_eq!(emulator.pixel(WIDTH - 1, HEIGHT - 1), Some(E6Color::Red));
+}
@@ -0,0 +1,139 @@
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::RgbColor;
+use epd_e6_driver::display::Error;
+use epd_e6_driver::prelude::*;
+
+/// Pushes all rows through the enhancer and collects the enhanced rows.
+fn enhance<F: PixelFilter>(
+    filter: F,
+    sharpening: f32,
+    width: usize,
+    image: &[Rgb888],
+) -> Vec<Vec<Rgb888>> {
+    let mut buffer = vec![Rgb888::BLACK; Enhancer::<F>::buffer_len(width)];
+    let mut enhancer = Enhancer::new(filter, width, &mut buffer)
+        .unwrap()
+        .with_sharpening(sharpening);
+    let mut rows = Vec::new();
+    for row in image.chunks(width) {
+        if let Some(enhanced) = enhancer.push_row(row).unwrap() {
+            rows.push(enhanced.to_vec());
+        }
+    }
+    rows.extend(enhancer.finish().map(<[Rgb888]>::to_vec));
+    assert_eq!(enhancer.finish(), None);
+    rows
+}
+
+#[test]
+fn neutral_filters_test() {
+    let filter = Tone::new().then(Saturation::new(1.0)).then(());
+    for value in [0, 1, 64, 127, 128, 200, 255] {
+        let color = Rgb888::new(value, 255 - value, value / 2);
+        assert_eq!(filter.apply(color), color);
+    }
+}
+
+#[test]
+fn tone_filter_test() {
+    let contrast = Tone::new().with_contrast(2.0);
+    assert_eq!(
+        contrast.apply(Rgb888::new(64, 128, 192)),
+        Rgb888::new(1, 129, 255)
+    );
+
+    let white_point = Tone::new().with_white_point(Rgb888::new(200, 220, 240));
+    assert_eq!(white_point.apply(Rgb888::new(200, 220, 240)), Rgb888::WHITE);
+    assert_eq!(
+        white_point.apply(Rgb888::new(100, 110, 120)),
+        Rgb888::new(128, 128, 128)
+    );
+
+    let gamma = Tone::new().with_gamma(2.2);
+    let midtone = gamma.apply(Rgb888::new(128, 128, 128));
+    assert!((185..=188).contains(&midtone.r()), "{midtone:?}");
+    assert_eq!(gamma.apply(Rgb888::BLACK), Rgb888::BLACK);
+    assert_eq!(gamma.apply(Rgb888::WHITE), Rgb888::WHITE);
+
+    let brightness = Tone::new().with_brightness(-0.5);
+    assert_eq!(
+        brightness.apply(Rgb888::new(255, 127, 0)),
+        Rgb888::new(128, 0, 0)
+    );
+}
+
+#[test]
+fn saturation_filter_test() {
+    let color = Rgb888::new(200, 100, 50);
+    let gray = Saturation::new(0.0).apply(color);
+    assert_eq!((gray.r(), gray.g()), (gray.b(), gray.b()));
+    let boosted = Saturation::new(2.0).apply(color);
+    assert!(boosted.r() > color.r() && boosted.g() < color.g() && boosted.b() < color.b());
+}
+
+#[test]
+fn enhancer_rows_test() {
+    let width = 3;
+    let image: Vec<Rgb888> = (0..15).map(|value| Rgb888::new(value, 0, 0)).collect();
+    let rows = enhance((), 0.0, width, &image);
+    assert_eq!(rows.concat(), image);
+
+    let filter = Saturation::new(0.0);
+    let rows = enhance(&filter, 0.0, width, &image[..width]);
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][2], filter.apply(image[2]));
+}
+
+#[test]
+fn sharpen_filter_test() {
+    let width = 4;
+    let flat = vec![Rgb888::new(100, 100, 100); width * 3];
+    assert_eq!(enhance((), 1.0, width, &flat).concat(), flat);
+
+    // Left half dark, right half light.
+    let edge: Vec<Rgb888> = (0..width * 3)
+        .map(|index| match index % width < 2 {
+            true => Rgb888::new(100, 100, 100),
+            false => Rgb888::new(150, 150, 150),
+        })
+        .collect();
+    let rows = enhance((), 1.0, width, &edge);
+    for row in rows {
+        assert_eq!(row[0], Rgb888::new(100, 100, 100));
+        assert!(row[1].r() < 100);
+        assert!(row[2].r() > 150);
+        assert_eq!(row[3], Rgb888::new(150, 150, 150));
+    }
+}
+
+#[test]
+fn enhancer_short_buffer_test() {
+    let mut buffer = [Rgb888::BLACK; 7];
+    assert_eq!(
+        Enhancer::new((), 2, &mut buffer).err(),
+        Some(Error::BufferTooSmall {
+            required: 8,
+            len: 7
+        })
+    );
+    let mut enhancer = Enhancer::new((), 1, &mut buffer).unwrap();
+    assert_eq!(
+        enhancer.push_row(&[]),
+        Err(Error::BufferTooSmall {
+            required: 1,
+            len: 0
+        })
+    );
+}
+
+#[test]
+fn enhanced_rows_dither_test() {
+    let width = 4;
+    let image = vec![Rgb888::new(200, 60, 60); width * 2];
+    let filter = Tone::new().with_contrast(1.5).then(Saturation::new(1.5));
+    let rows = enhance(&filter, 0.5, width, &image);
+    let ditherer = Ditherer::new(DitherMethod::Bayer, width, &mut []).unwrap();
+    let colors: Vec<E6Color> = ditherer.dither(rows.concat()).collect();
+    assert_eq!(colors.len(), image.len());
+    assert!(colors.contains(&E6Color::Red));
+}
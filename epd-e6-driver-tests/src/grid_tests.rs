@@ -0,0 +1,170 @@
+use embedded_graphics::prelude::{Point, Size};
+use embedded_graphics::primitives::Rectangle;
+use epd_e6_driver::display::Error;
+use epd_e6_driver::prelude::*;
+
+type NibbleGrid = Grid<[u8; 8], E6Color>;
+
+#[test]
+fn grid_stride_test() {
+    assert_eq!(NibbleGrid::padded_stride(3), 4);
+    assert_eq!(NibbleGrid::padded_stride(4), 4);
+    assert_eq!(Grid::<[u8; 1], u8, 1>::padded_stride(9), 16);
+
+    let mut grid = NibbleGrid::with_stride([0; 8], 3, 4, NibbleGrid::padded_stride(3));
+    grid.set(0, 0, E6Color::Red);
+    grid.set(2, 0, E6Color::Blue);
+    grid.set(1, 3, E6Color::Green);
+
+    assert_eq!(
+        grid.buffer().as_underlying_data(),
+        &[0x30, 0x50, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00]
+    );
+    assert_eq!(grid.get(2, 0), E6Color::Blue);
+    assert_eq!(grid.row_data(3), Some(&[0x06, 0x00][..]));
+    grid.row_data_mut(1).unwrap().copy_from_slice(&[0x22, 0x20]);
+    assert_eq!(grid.get(2, 1), E6Color::Yellow);
+    assert_eq!(grid.get(0, 2), E6Color::Black);
+
+    let unpadded = NibbleGrid::new([0; 8], 3, 4);
+    assert_eq!(unpadded.row_data(0), None);
+    assert_eq!(unpadded.row_data(4), None);
+}
+
+#[test]
+fn grid_out_of_bounds_test() {
+    assert!(matches!(
+        NibbleGrid::try_new([0; 8], 5, 4),
+        Err(Error::BufferTooSmall {
+            required: 20,
+            len: 16
+        })
+    ));
+    assert!(matches!(
+        NibbleGrid::try_with_stride([0; 8], 4, 2, 3),
+        Err(Error::InvalidStride {
+            stride: 3,
+            width: 4
+        })
+    ));
+
+    let mut grid = NibbleGrid::new([0; 8], 4, 4);
+    assert!(matches!(
+        grid.try_get(4, 0),
+        Err(Error::OutOfBounds { index: 4, len: 16 })
+    ));
+    assert!(grid.try_set(0, 4, E6Color::Red).is_err());
+    assert!(grid.try_set(3, 3, E6Color::Red).is_ok());
+    assert_eq!(grid.try_get(3, 3).unwrap(), E6Color::Red);
+}
+
+#[test]
+fn grid_view_test() {
+    let mut grid = NibbleGrid::new([0x11; 8], 4, 4);
+    let mut view = grid.view_mut(&Rectangle::new(Point::new(1, 1), Size::new(2, 2)));
+    view.fill(E6Color::Red);
+    view.set(1, 1, E6Color::Blue);
+    assert!(view.try_set(2, 0, E6Color::Blue).is_err());
+
+    let mut inner = view.view_mut(&Rectangle::new(Point::new(1, 0), Size::new(5, 5)));
+    assert_eq!((inner.width(), inner.height()), (1, 2));
+    inner.set(0, 0, E6Color::Green);
+
+    assert_eq!(
+        grid.buffer().as_underlying_data(),
+        &[0x11, 0x11, 0x13, 0x61, 0x13, 0x51, 0x11, 0x11]
+    );
+
+    let view = grid.view(&Rectangle::new(Point::new(-2, 2), Size::new(4, 9)));
+    assert_eq!((view.width(), view.height()), (2, 2));
+    assert_eq!(view.get(1, 0), E6Color::Red);
+    let clipped = grid.view(&Rectangle::new(Point::new(4, 0), Size::new(2, 2)));
+    assert_eq!((clipped.width(), clipped.height()), (0, 0));
+    assert_eq!(clipped.iter().count(), 0);
+}
+
+#[test]
+fn grid_iterators_test() {
+    let mut grid = NibbleGrid::new([0; 8], 3, 3);
+    grid.update(|(x, y), _| if x == y { E6Color::Red } else { E6Color::White });
+
+    let view = grid.view(&Rectangle::new(Point::new(1, 0), Size::new(2, 3)));
+    let mut iter = view.iter();
+    assert_eq!(iter.len(), 6);
+    assert_eq!(iter.next(), Some(((0, 0), E6Color::White)));
+    assert_eq!(iter.next_back(), Some(((1, 2), E6Color::Red)));
+    assert_eq!(iter.len(), 4);
+    assert_eq!(
+        iter.rev().collect::<Vec<_>>(),
+        [
+            ((0, 2), E6Color::White),
+            ((1, 1), E6Color::White),
+            ((0, 1), E6Color::Red),
+            ((1, 0), E6Color::White),
+        ]
+    );
+
+    let mut rows = grid.rows();
+    assert_eq!(rows.len(), 3);
+    let last = rows.next_back().unwrap();
+    assert_eq!(
+        last.into_iter().map(|(_, color)| color).collect::<Vec<_>>(),
+        [E6Color::White, E6Color::White, E6Color::Red]
+    );
+    let diagonal: Vec<_> = grid
+        .rows()
+        .enumerate()
+        .map(|(y, row)| row.get(y, 0))
+        .collect();
+    assert_eq!(diagonal, [E6Color::Red; 3]);
+    assert_eq!(rows.len(), 2);
+}
+
+#[test]
+fn grid_blit_clipped_test() {
+    let mut image = Grid::<[u8; 4], E6Color>::new([0; 4], 4, 2);
+    image.update(|(x, y), _| [E6Color::Red, E6Color::White, E6Color::Blue][(x + y) % 3]);
+    let sprite = Sprite::new(image.as_view());
+
+    let mut aligned = Grid::<[u8; 12], E6Color>::new([0x11; 12], 6, 4);
+    aligned.blit(&sprite, Point::new(2, 1));
+    assert_eq!(
+        aligned.buffer().as_underlying_data(),
+        &[
+            0x11, 0x11, 0x11, 0x11, 0x31, 0x53, 0x11, 0x15, 0x31, 0x11, 0x11, 0x11
+        ]
+    );
+
+    let mut unaligned = Grid::<[u8; 12], E6Color>::new([0x11; 12], 6, 4);
+    unaligned.blit(&sprite, Point::new(-1, 3));
+    assert_eq!(
+        unaligned.buffer().as_underlying_data(),
+        &[
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x15, 0x31, 0x11
+        ]
+    );
+
+    let mut keyed = Grid::<[u8; 12], E6Color>::new([0x22; 12], 6, 4);
+    let mut view = keyed.view_mut(&Rectangle::new(Point::new(1, 1), Size::new(4, 2)));
+    view.blit(&sprite.with_color_key(E6Color::White), Point::new(1, 0));
+    assert_eq!(
+        keyed.buffer().as_underlying_data(),
+        &[
+            0x22, 0x22, 0x22, 0x22, 0x32, 0x52, 0x22, 0x25, 0x32, 0x22, 0x22, 0x22
+        ]
+    );
+}
+
+#[test]
+fn grid_blit_mask_test() {
+    let image = Grid::<[u8; 2], E6Color>::new([0x35; 2], 2, 2);
+    let mut mask = Grid::<[u8; 1], u8, 1>::new([0; 1], 2, 2);
+    mask.set(0, 0, 1);
+    mask.set(1, 1, 1);
+    let sprite = Sprite::new(image.as_view())
+        .with_mask(mask.view(&Rectangle::new(Point::zero(), Size::new(2, 1))));
+
+    let mut grid = Grid::<[u8; 2], E6Color>::new([0x11; 2], 2, 2);
+    grid.blit(&sprite, Point::zero());
+    assert_eq!(grid.buffer().as_underlying_data(), &[0x31, 0x11]);
+}
@@ -1,9 +1,39 @@
+#[cfg(test)]
+mod display_tests;
+#[cfg(test)]
+mod dither_tests;
+#[cfg(test)]
+mod enhance_tests;
+#[cfg(test)]
+mod grid_tests;
+#[cfg(test)]
+mod quantize_tests;
+
+/// The driver logs through defmt, which needs a global logger to link on the host.
+#[defmt::global_logger]
+struct NoopLogger;
+
+unsafe impl defmt::Logger for NoopLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("");
+
 fn main() {
     println!("Run with cargo test -p epd-e6-driver-tests --target x86_64-unknown-linux-gnu");
 }
 
 #[cfg(test)]
 mod tests {
+    use embedded_graphics::pixelcolor::Rgb888;
+    use embedded_graphics::pixelcolor::raw::{RawData, RawU4};
+    use epd_e6_driver::display::{Error, InvalidColor};
     use epd_e6_driver::prelude::*;
 
     #[test]
@@ -31,6 +61,75 @@ mod tests {
         let _: Nibbles<_, u8> = Nibbles::new([0u8; 32], 65);
     }
 
+    #[test]
+    fn fill_nibbles_test() {
+        let mut nibbles: Nibbles<_, u8> = Nibbles::new([0u8; 5], 9);
+        nibbles.fill(1..8, 0x0A);
+        assert_eq!(
+            nibbles.as_underlying_data(),
+            &[0x0A, 0xAA, 0xAA, 0xAA, 0x00]
+        );
+        nibbles.fill(6..20, 0x03);
+        assert_eq!(
+            nibbles.as_underlying_data(),
+            &[0x0A, 0xAA, 0xAA, 0x33, 0x30]
+        );
+        nibbles.fill(3..3, 0x01);
+        nibbles.fill(3..4, 0x01);
+        assert_eq!(
+            nibbles.as_underlying_data(),
+            &[0x0A, 0xA1, 0xAA, 0x33, 0x30]
+        );
+    }
+
+    #[test]
+    fn packed_buffer_bit_orders_test() {
+        let mut msb: PackedBuffer<_, u8, 1> = PackedBuffer::new([0u8; 2], 10);
+        let mut lsb: PackedBuffer<_, u8, 1, LsbFirst> = PackedBuffer::new([0u8; 2], 10);
+        for index in [0, 3, 9] {
+            msb.set(index, 1);
+            lsb.set(index, 1);
+        }
+        assert_eq!(msb.as_underlying_data(), &[0b1001_0000, 0b0100_0000]);
+        assert_eq!(lsb.as_underlying_data(), &[0b0000_1001, 0b0000_0010]);
+        let bits: Vec<u8> = msb.iter().collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bits, lsb.iter().collect::<Vec<u8>>());
+
+        let mut crumbs: PackedBuffer<_, u8, 2, LsbFirst> = PackedBuffer::new([0u8; 2], 7);
+        for index in 0..crumbs.len() {
+            crumbs.set(index, index as u8 % 4);
+        }
+        assert_eq!(crumbs.as_underlying_data(), &[0b1110_0100, 0b0010_0100]);
+        assert_eq!(crumbs.get(6), 2);
+
+        let mut bytes: PackedBuffer<_, u8, 8> = PackedBuffer::new([0u8; 3], 3);
+        bytes.set(1, 0xAB);
+        assert_eq!(bytes.as_underlying_data(), &[0, 0xAB, 0]);
+    }
+
+    #[test]
+    fn packed_buffer_fill_and_errors_test() {
+        let mut bits: PackedBuffer<_, u8, 1> = PackedBuffer::new([0u8; 3], 24);
+        bits.fill(3..21, 1);
+        assert_eq!(bits.as_underlying_data(), &[0b0001_1111, 0xFF, 0b1111_1000]);
+        bits.fill(5..6, 0);
+        assert_eq!(bits.as_underlying_data(), &[0b0001_1011, 0xFF, 0b1111_1000]);
+
+        assert_eq!(
+            PackedBuffer::<_, u8, 2>::try_new([0u8; 2], 9).err(),
+            Some(Error::BufferTooSmall {
+                required: 9,
+                len: 8
+            })
+        );
+        let mut crumbs: PackedBuffer<_, u8, 2> = PackedBuffer::new([0u8; 2], 8);
+        assert_eq!(
+            crumbs.try_set(8, 1),
+            Err(Error::OutOfBounds { index: 8, len: 8 })
+        );
+    }
+
     #[test]
     fn get_set_nibbles_test() {
         let mut nibbles: Nibbles<_, u8> = Nibbles::new([0u8; 4], 7);
@@ -44,4 +143,67 @@ mod tests {
             assert_eq!(nibbles.get(i), i as u8 % 0x0F);
         }
     }
+
+    #[test]
+    fn try_nibbles_report_errors() {
+        let result: Result<Nibbles<_, u8>, _> = Nibbles::try_new([0u8; 4], 9);
+        assert!(matches!(
+            result,
+            Err(Error::BufferTooSmall {
+                required: 9,
+                len: 8
+            })
+        ));
+
+        let mut nibbles: Nibbles<_, u8> = Nibbles::try_new([0u8; 4], 7).unwrap();
+        nibbles.try_set(6, 0x0A).unwrap();
+        assert_eq!(nibbles.try_get(6).unwrap(), 0x0A);
+        assert_eq!(
+            nibbles.try_get(7).unwrap_err(),
+            Error::OutOfBounds { index: 7, len: 7 }
+        );
+        assert!(nibbles.try_set(7, 0).is_err());
+    }
+
+    #[test]
+    fn e6_color_conversions() {
+        for color in [
+            E6Color::Black,
+            E6Color::White,
+            E6Color::Yellow,
+            E6Color::Red,
+            E6Color::Blue,
+            E6Color::Green,
+        ] {
+            assert_eq!(E6Color::try_from_index(color as u8).unwrap(), color);
+            assert_eq!(E6Color::try_from_rgb(Rgb888::from(color)).unwrap(), color);
+        }
+        #[cfg(not(feature = "calibrated-palette"))]
+        {
+            assert_eq!(Rgb888::from(E6Color::Blue), Rgb888::new(0, 0, 255));
+            assert_eq!(Rgb888::from(E6Color::Green), Rgb888::new(0, 255, 0));
+        }
+        assert_eq!(
+            E6Color::try_from_index(4).unwrap_err(),
+            Error::InvalidColor(InvalidColor::Index(4))
+        );
+        assert_eq!(
+            E6Color::try_from_rgb(Rgb888::new(1, 2, 3)).unwrap_err(),
+            Error::InvalidColor(InvalidColor::Rgb(1, 2, 3))
+        );
+    }
+
+    #[test]
+    fn e6_color_raw_round_trip() {
+        for index in 0..16 {
+            let color = E6Color::from(RawU4::new(index));
+            match E6Color::try_from_index(index) {
+                Ok(expected) => {
+                    assert_eq!(color, expected);
+                    assert_eq!(RawU4::from(color).into_inner(), index);
+                }
+                Err(_) => assert_eq!(color, E6Color::White),
+            }
+        }
+    }
 }
@@ -0,0 +1,162 @@
+use embedded_graphics::pixelcolor::{Bgr888, Rgb565, Rgb888};
+use epd_e6_driver::display::AsRgbColor;
+use epd_e6_driver::prelude::*;
+
+const COLORS: [E6Color; 6] = [
+    E6Color::Black,
+    E6Color::White,
+    E6Color::Yellow,
+    E6Color::Red,
+    E6Color::Blue,
+    E6Color::Green,
+];
+
+#[test]
+fn quantize_palette_colors_test() {
+    for color in COLORS {
+        let rgb = Rgb888::from(color);
+        assert_eq!(quantize(rgb), color);
+        assert_eq!(E6Color::from(rgb), color);
+        assert_eq!(E6Color::from(Rgb565::from(rgb)), color);
+        assert_eq!(E6Color::from(Bgr888::from(rgb)), color);
+        assert_eq!(nearest(rgb, Distance::OkLab), color);
+        assert_eq!(nearest(rgb, Distance::WeightedRgb), color);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "calibrated-palette"))]
+fn quantize_nearest_color_test() {
+    use embedded_graphics::pixelcolor::Gray8;
+
+    let cases = [
+        (Rgb888::new(250, 10, 5), E6Color::Red),
+        (Rgb888::new(30, 40, 200), E6Color::Blue),
+        (Rgb888::new(20, 200, 60), E6Color::Green),
+        (Rgb888::new(240, 230, 40), E6Color::Yellow),
+        (Rgb888::new(235, 235, 240), E6Color::White),
+        (Rgb888::new(15, 10, 20), E6Color::Black),
+    ];
+    for (rgb, color) in cases {
+        assert_eq!(E6Color::from(rgb), color, "{rgb:?}");
+        assert_eq!(nearest(rgb, Distance::OkLab), color, "{rgb:?}");
+        assert_eq!(nearest(rgb, Distance::WeightedRgb), color, "{rgb:?}");
+    }
+    assert_eq!(E6Color::from(Gray8::new(230)), E6Color::White);
+    assert_eq!(E6Color::from(Gray8::new(40)), E6Color::Black);
+}
+
+#[test]
+fn quantize_table_test() {
+    for r in 0..16u8 {
+        for g in 0..16u8 {
+            for b in 0..16u8 {
+                let rgb = Rgb888::new(r * 16 + 8, g * 16 + 8, b * 16 + 8);
+                assert_eq!(quantize(rgb), nearest(rgb, Distance::OkLab), "{rgb:?}");
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "calibrated-palette"))]
+const DEFAULT_PALETTE: Palette = Palette::IDEAL;
+#[cfg(feature = "calibrated-palette")]
+const DEFAULT_PALETTE: Palette = Palette::SPECTRA6;
+
+#[test]
+fn default_palette_test() {
+    assert_eq!(Palette::default(), DEFAULT_PALETTE);
+    for color in COLORS {
+        assert_eq!(DEFAULT_PALETTE.rgb(color), color.rgb_color());
+    }
+}
+
+#[test]
+fn default_palette_switch_test() {
+    #[cfg(not(feature = "calibrated-palette"))]
+    let (r, g, b) = (255, 0, 0);
+    #[cfg(feature = "calibrated-palette")]
+    let (r, g, b) = (178, 19, 24);
+    let red = Rgb888::new(r, g, b);
+    assert_eq!(Rgb888::from(E6Color::Red), red);
+    assert_eq!(E6Color::Red.rgb_color(), (r, g, b));
+    assert_eq!(quantize(red), E6Color::Red);
+
+    let mut errors = [[0; 3]; 16];
+    let ditherer = Ditherer::new(DitherMethod::FloydSteinberg, 8, &mut errors).unwrap();
+    assert!(
+        ditherer
+            .dither([red; 16])
+            .all(|color| color == E6Color::Red)
+    );
+}
+
+#[test]
+fn calibrated_palette_test() {
+    for color in COLORS {
+        let (r, g, b) = Palette::SPECTRA6.rgb(color);
+        let measured = Rgb888::new(r, g, b);
+        assert_eq!(Palette::SPECTRA6.nearest(measured, Distance::OkLab), color);
+        assert_eq!(
+            Palette::SPECTRA6.nearest(measured, Distance::WeightedRgb),
+            color
+        );
+    }
+    let dark_red = Rgb888::new(140, 20, 25);
+    assert_eq!(
+        Palette::SPECTRA6.nearest(dark_red, Distance::OkLab),
+        E6Color::Red
+    );
+}
+
+#[test]
+fn runtime_palette_test() {
+    static SPECTRA6_TABLE: QuantizeTable = QuantizeTable::new(&Palette::SPECTRA6);
+    for r in 0..16u8 {
+        for g in 0..16u8 {
+            for b in 0..16u8 {
+                let rgb = Rgb888::new(r * 16 + 8, g * 16 + 8, b * 16 + 8);
+                let expected = Palette::SPECTRA6.nearest(rgb, Distance::OkLab);
+                assert_eq!(SPECTRA6_TABLE.quantize(rgb), expected, "{rgb:?}");
+                assert_eq!(quantize_with(&Palette::SPECTRA6, rgb), expected, "{rgb:?}");
+            }
+        }
+    }
+
+    let dark_red = Rgb888::new(140, 20, 25);
+    assert_eq!(quantize(dark_red), E6Color::Red);
+    assert_eq!(quantize_with(&Palette::SPECTRA6, dark_red), E6Color::Red);
+    for color in COLORS {
+        let rgb = Palette::SPECTRA6.to_rgb888(color);
+        assert_eq!(Palette::SPECTRA6.try_color(rgb), Ok(color));
+        assert_eq!(DEFAULT_PALETTE.to_rgb888(color), Rgb888::from(color));
+    }
+    assert!(Palette::SPECTRA6.try_color(Rgb888::new(255, 0, 0)).is_err());
+    assert_eq!(
+        E6Color::try_from_rgb(DEFAULT_PALETTE.to_rgb888(E6Color::Red)),
+        Ok(E6Color::Red)
+    );
+}
+
+#[test]
+fn ditherer_custom_palette_test() {
+    let palette = Palette::new([
+        (10, 10, 10),
+        (200, 200, 200),
+        (200, 190, 60),
+        (150, 30, 30),
+        (40, 60, 150),
+        (30, 100, 40),
+    ]);
+    for method in [DitherMethod::FloydSteinberg, DitherMethod::SierraLite] {
+        let mut errors = [[0; 3]; 16];
+        let ditherer = Ditherer::new(method, 8, &mut errors)
+            .unwrap()
+            .with_palette(palette);
+        let pixels = [Rgb888::new(150, 30, 30); 16];
+        assert!(
+            ditherer.dither(pixels).all(|color| color == E6Color::Red),
+            "{method:?}"
+        );
+    }
+}
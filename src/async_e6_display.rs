@@ -1,34 +1,148 @@
-use crate::display::{AsyncDisplay, AsyncPartialUpdate, Display, Error};
-use crate::e6_display::{
-    CommandCode, DataCommand, E6Color, INIT_SEQUENCE, RESET_DELAY_MS, set_data_command,
-};
-use crate::nibbles::{Nibbles, underlying_data_len};
+use crate::band::Band;
+use crate::busy::{BusyHook, NoBusyHook, async_busy_wait};
+use crate::display::{AsyncDisplay, AsyncPartialUpdate, Display, Error, PinRole, PowerState};
+use crate::display_core::{DisplayCore, Source};
+use crate::e6_display::E6Color;
+use crate::nibbles::Nibbles;
+use crate::orientation::Orientation;
+use crate::profile::PanelProfile;
+use crate::protocol::{Operation, Protocol, Step};
+use crate::sprite::Sprite;
+use crate::transport::{AsyncTransport, FourWire};
 use core::ops::RangeInclusive;
 use defmt::info;
 use embedded_graphics::Pixel;
 use embedded_graphics::draw_target::DrawTarget;
-use embedded_graphics::geometry::{OriginDimensions, Size};
-use embedded_hal::digital::OutputPin;
+use embedded_graphics::geometry::{OriginDimensions, Point, Size};
+use embedded_graphics::primitives::Rectangle;
+use embedded_hal::digital::{OutputPin, PinState};
 use embedded_hal_async::delay::DelayNs;
 use embedded_hal_async::digital::Wait;
 use embedded_hal_async::spi::SpiDevice;
 
 pub struct AsyncE6Display<
-    DC: OutputPin,
+    T: AsyncTransport,
     RST: OutputPin,
     BUSY: Wait,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook = NoBusyHook,
 > {
-    spi: SPI,
-    dc_pin: DC,
+    executor: AsyncExecutor<T, RST, BUSY, DELAY, H>,
+    core: DisplayCore<S>,
+}
+
+/// Transport, control lines and delay source of the async driver, which execute the steps of
+/// the protocol.
+struct AsyncExecutor<T, RST, BUSY, DELAY, H> {
+    transport: T,
     rst_pin: RST,
     busy_pin: BUSY,
-    width: u16,
-    height: u16,
     delay_source: DELAY,
-    frame_buffer: Nibbles<S, E6Color>,
+    busy_hook: H,
+}
+
+impl<T, RST, BUSY, DELAY> AsyncExecutor<T, RST, BUSY, DELAY, NoBusyHook> {
+    fn new(transport: T, rst_pin: RST, busy_pin: BUSY, delay_source: DELAY) -> Self {
+        Self {
+            transport,
+            rst_pin,
+            busy_pin,
+            delay_source,
+            busy_hook: NoBusyHook,
+        }
+    }
+}
+
+impl<T, RST, BUSY, DELAY, H> AsyncExecutor<T, RST, BUSY, DELAY, H> {
+    fn with_busy_hook<HOOK>(self, busy_hook: HOOK) -> AsyncExecutor<T, RST, BUSY, DELAY, HOOK> {
+        AsyncExecutor {
+            transport: self.transport,
+            rst_pin: self.rst_pin,
+            busy_pin: self.busy_pin,
+            delay_source: self.delay_source,
+            busy_hook,
+        }
+    }
+}
+
+impl<T: AsyncTransport, RST: OutputPin, BUSY: Wait, DELAY: DelayNs, H: BusyHook>
+    AsyncExecutor<T, RST, BUSY, DELAY, H>
+{
+    /// Executes the steps of the operation, sending the windows from `source`.
+    async fn run<S: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        core: &mut DisplayCore<S>,
+        operation: Operation,
+        source: &mut impl Source,
+    ) -> Result<(), Error> {
+        let (protocol, frame_buffer) = core.protocol_and_frame();
+        let busy_policy = protocol.profile.busy_policy;
+        let mut sequence = protocol.sequence(operation)?;
+        let mut command = None;
+        while let Some(step) = sequence.next() {
+            let result = match step {
+                Step::Reset(active) => self
+                    .rst_pin
+                    .set_state(PinState::from(!active))
+                    .map_err(|err| Error::from_digital_pin_error(err).on_pin(PinRole::Reset)),
+                Step::DelayMs(ms) => {
+                    self.delay_source.delay_ms(ms).await;
+                    Ok(())
+                }
+                // The protocol of a single controller never selects one.
+                Step::Select(_) => Ok(()),
+                Step::Command(code) => {
+                    command = Some(code);
+                    self.transport.write_command(code).await
+                }
+                Step::Data(data) => {
+                    info!("Sending data chunk: {}", data.len());
+                    self.transport.write_data(data).await
+                }
+                Step::CommandWithStatus(code) => {
+                    command = Some(code);
+                    let mut status = [0u8; 1];
+                    self.transport
+                        .write_command_with_status(code, &mut status)
+                        .await
+                        .inspect(|()| info!("Command {} status: {}", code, status))
+                }
+                Step::FrameBuffer(window) => {
+                    info!("Sending window: {}", window.command_data());
+                    source.start(window);
+                    send(&mut self.transport, source, frame_buffer).await
+                }
+                Step::WaitBusy(phase) => {
+                    async_busy_wait(
+                        &mut self.busy_pin,
+                        &mut self.delay_source,
+                        &mut self.busy_hook,
+                        &busy_policy,
+                        phase,
+                    )
+                    .await
+                }
+            };
+            result.map_err(|error| match command {
+                Some(command) => error.during(command),
+                None => error,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the chunks of the current window of `source`.
+async fn send(
+    transport: &mut impl AsyncTransport,
+    source: &mut impl Source,
+    frame_buffer: &[u8],
+) -> Result<(), Error> {
+    while let Some(chunk) = source.next_chunk(frame_buffer)? {
+        transport.write_data(chunk).await?;
+    }
+    Ok(())
 }
 
 #[allow(dead_code)]
@@ -39,8 +153,10 @@ impl<
     SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> AsyncE6Display<DC, RST, BUSY, SPI, DELAY, S>
+> AsyncE6Display<FourWire<SPI, DC>, RST, BUSY, DELAY, S>
 {
+    /// Creates a driver for a 7.3" compatible panel with the given resolution.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: u16,
         height: u16,
@@ -51,145 +167,239 @@ impl<
         delay_source: DELAY,
         frame_buffer: Nibbles<S, E6Color>,
     ) -> Self {
-        assert!(
-            frame_buffer.len() >= width as usize * height as usize,
-            "Frame Buffer has not enough space for all pixels"
-        );
-        Self {
+        Self::with_profile(
+            PanelProfile::SPECTRA6_7IN3.with_resolution(width, height),
             spi,
             dc_pin,
             rst_pin,
             busy_pin,
-            width,
-            height,
             delay_source,
             frame_buffer,
-        }
+        )
     }
 
-    async fn power_off(&mut self) -> Result<(), Error> {
-        self.spi_write_command(CommandCode::POF).await
+    pub fn with_profile(
+        profile: PanelProfile,
+        spi: SPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        Self::with_transport(
+            profile,
+            FourWire::new(spi, dc_pin),
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
     }
 
-    async fn power_on(&mut self) -> Result<(), Error> {
-        self.spi_write_command(CommandCode::PON).await
+    pub fn try_with_profile(
+        profile: PanelProfile,
+        spi: SPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        Self::try_with_transport(
+            profile,
+            FourWire::new(spi, dc_pin),
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
     }
+}
 
-    async fn display_refresh(&mut self) -> Result<(), Error> {
-        self.spi_write_command_and_data(CommandCode::DRF, &[0x00])
-            .await
+#[allow(dead_code)]
+impl<T: AsyncTransport, RST: OutputPin, BUSY: Wait, DELAY: DelayNs, S: AsMut<[u8]> + AsRef<[u8]>>
+    AsyncE6Display<T, RST, BUSY, DELAY, S>
+{
+    /// Creates a driver that talks to the controller through the given transport, e.g.
+    /// [`ThreeWire`](crate::transport::ThreeWire).
+    pub fn with_transport(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        match Self::try_with_transport(
+            profile,
+            transport,
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        ) {
+            Ok(display) => display,
+            Err(error) => panic!("{}", error),
+        }
     }
 
-    fn set_data_command(&mut self, data_command: DataCommand) -> Result<(), Error> {
-        set_data_command(&mut self.dc_pin, data_command)
+    pub fn try_with_transport(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            executor: AsyncExecutor::new(transport, rst_pin, busy_pin, delay_source),
+            core: DisplayCore::try_new(Protocol::new(profile), frame_buffer)?,
+        })
     }
+}
 
-    async fn spi_write_command(&mut self, command: CommandCode) -> Result<(), Error> {
-        self.set_data_command(DataCommand::Command)?;
-        self.spi
-            .write(&[command as u8])
-            .await
-            .map_err(Error::from_spi_error)
+impl<T: AsyncTransport, RST: OutputPin, BUSY: Wait, DELAY: DelayNs>
+    AsyncE6Display<T, RST, BUSY, DELAY, [u8; 0]>
+{
+    /// Creates a driver that keeps no image. Drawing to it does nothing, and the panel is
+    /// refreshed with [`AsyncE6Display::refresh_from_fn`] or
+    /// [`AsyncE6Display::refresh_from_rows`].
+    pub fn without_frame_buffer(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+    ) -> Self {
+        Self {
+            executor: AsyncExecutor::new(transport, rst_pin, busy_pin, delay_source),
+            core: DisplayCore::without_frame_buffer(Protocol::new(profile)),
+        }
     }
+}
 
-    async fn spi_write_data(&mut self, data: &[u8]) -> Result<(), Error> {
-        info!("Sending data chunk: {}", data.len());
-        self.set_data_command(DataCommand::Data)?;
-        self.spi.write(&data).await.map_err(Error::from_spi_error)?;
-        Ok(())
+#[allow(dead_code)]
+impl<
+    T: AsyncTransport,
+    RST: OutputPin,
+    BUSY: Wait,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> AsyncE6Display<T, RST, BUSY, DELAY, S, H>
+{
+    /// Runs the hook on every poll interval while the driver waits for the BUSY line.
+    pub fn with_busy_hook<HOOK: BusyHook>(
+        self,
+        busy_hook: HOOK,
+    ) -> AsyncE6Display<T, RST, BUSY, DELAY, S, HOOK> {
+        AsyncE6Display {
+            executor: self.executor.with_busy_hook(busy_hook),
+            core: self.core,
+        }
     }
 
-    async fn spi_write_frame_buffer(&mut self) -> Result<(), Error> {
-        self.set_data_command(DataCommand::Data)?;
-        let len = underlying_data_len(self.frame_buffer.len());
-        let frame_buffer_data = &self.frame_buffer.as_underlying_data().as_ref()[0..len];
-        info!("Sending data chunk: {}", len);
-        self.spi
-            .write(frame_buffer_data)
-            .await
-            .map_err(Error::from_spi_error)?;
-        Ok(())
+    pub fn orientation(&self) -> Orientation {
+        self.core.orientation()
     }
 
-    async fn spi_write_command_and_read<const R: usize>(
-        &mut self,
-        command: CommandCode,
-    ) -> Result<[u8; R], Error> {
-        let mut result = [0u8; R];
-        self.set_data_command(DataCommand::Command)?;
-        self.spi
-            .transfer(&mut result, &[command as u8])
-            .await
-            .map_err(Error::from_spi_error)?;
-        Ok(result)
-    }
-
-    async fn spi_write_command_and_data(
+    /// Changes how drawing coordinates map to the panel. The frame buffer keeps its content,
+    /// only later drawing uses the new orientation.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.core.set_orientation(orientation);
+    }
+
+    /// Replaces the frame buffer with a frame already packed in the controller format, two
+    /// pixels per byte with the first one in the high nibble. The frame is in the native layout
+    /// of the panel, the orientation doesn't apply.
+    pub fn update_packed(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.core.update_packed(data)
+    }
+
+    /// Replaces a window of the frame buffer, given in native panel coordinates, with packed
+    /// rows like [`AsyncE6Display::update_packed`]. Every row starts on a byte boundary, so
+    /// panels with an odd width are rejected.
+    pub fn partial_update_packed(
         &mut self,
-        command: CommandCode,
         data: &[u8],
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
     ) -> Result<(), Error> {
-        self.spi_write_command(command).await?;
-        self.spi_write_data(data).await?;
-        Ok(())
+        self.core.partial_update_packed(data, horizontal, vertical)
     }
 
-    async fn reset(&mut self) -> Result<(), Error> {
-        self.rst_pin
-            .set_low()
-            .map_err(Error::from_digital_pin_error)?;
-        self.delay_source.delay_ms(RESET_DELAY_MS).await;
-        self.rst_pin
-            .set_high()
-            .map_err(Error::from_digital_pin_error)?;
-        self.delay_source.delay_ms(RESET_DELAY_MS).await;
-        self.busy_wait().await?;
-        Ok(())
+    /// Draws `sprite` with its top left corner at the logical `position`, clipped to the panel.
+    /// The frame buffer has to hold the whole panel.
+    pub fn blit<I: AsMut<[u8]> + AsRef<[u8]>, M: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        sprite: &Sprite<'_, I, E6Color, M>,
+        position: Point,
+    ) -> Result<(), Error> {
+        self.core.blit(sprite, position)
     }
 
-    async fn busy_wait(&mut self) -> Result<(), Error> {
-        info!("The display could be busy, waiting...");
-        self.busy_pin
-            .wait_for_high()
-            .await
-            .map_err(Error::from_digital_pin_error)?;
-        info!("The display is free, continue...");
-        Ok(())
+    /// Refreshes the whole panel with pixels computed on the fly instead of the frame buffer,
+    /// e.g. on a driver created with [`AsyncE6Display::without_frame_buffer`]. `pixel` is
+    /// called once per logical point, in the panel's scan order.
+    pub async fn refresh_from_fn(
+        &mut self,
+        pixel: impl FnMut(Point) -> E6Color,
+    ) -> Result<(), Error> {
+        let mut pixels = self.core.pixels(pixel);
+        self.run_with(Operation::Refresh, &mut pixels).await
     }
 
-    async fn refresh_display(&mut self) -> Result<(), Error> {
-        self.power_on().await?;
-        self.busy_wait().await?;
-        //self.spi_write_command_and_data(CommandCode::BTST2, &[0x6F, 0x1F, 0x17, 0x49])?;
-        self.display_refresh().await?;
-        self.busy_wait().await?;
-        self.power_off().await?;
-        self.busy_wait().await?;
-        Ok(())
+    /// Refreshes the whole panel row by row from `fill_row`, which gets the panel row index
+    /// and a row of `row_buffer` to fill, in panel coordinates regardless of the orientation.
+    /// `row_buffer` needs half a byte per pixel of a row, and the panel width must be even.
+    pub async fn refresh_from_rows(
+        &mut self,
+        row_buffer: &mut [u8],
+        fill_row: impl FnMut(u16, &mut Nibbles<&mut [u8], E6Color>),
+    ) -> Result<(), Error> {
+        let mut rows = self.core.rows(row_buffer, fill_row)?;
+        self.run_with(Operation::Refresh, &mut rows).await
     }
 
-    async fn send_frame_buffer(&mut self) -> Result<(), Error> {
-        self.spi_write_command(CommandCode::DTM1).await?;
-        self.spi_write_frame_buffer().await?;
-        let result: [u8; 1] = self.spi_write_command_and_read(CommandCode::DSP).await?;
-        info!("Frame buffer sent, result: {}", result);
-        self.busy_wait().await?;
-        Ok(())
+    /// Refreshes the whole panel in horizontal bands that fit into `band_buffer`, without
+    /// touching the frame buffer. For every band, the band is filled with `background` and
+    /// `draw` renders the whole logical display into it, keeping only the band's pixels.
+    /// The panel width must be even and the buffer must hold at least one panel row.
+    pub async fn refresh_banded(
+        &mut self,
+        band_buffer: &mut [u8],
+        background: E6Color,
+        draw: impl FnMut(&mut Band<'_>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut bands = self.core.bands(band_buffer, background, draw)?;
+        self.run_with(Operation::Refresh, &mut bands).await
     }
 
-    fn pixel_index(&self, x: usize, y: usize) -> usize {
-        y * self.width as usize + x
+    /// Executes the steps of the operation, sending the frame buffer.
+    async fn run(&mut self, operation: Operation) -> Result<(), Error> {
+        let mut frame_buffer = self.core.frame_buffer();
+        self.run_with(operation, &mut frame_buffer).await
+    }
+
+    /// Executes the steps of the operation, sending the windows from `source`.
+    async fn run_with(
+        &mut self,
+        operation: Operation,
+        source: &mut impl Source,
+    ) -> Result<(), Error> {
+        self.executor.run(&mut self.core, operation, source).await
     }
 }
 
 impl<
-    DC: OutputPin + Send,
-    RST: OutputPin + Send,
-    BUSY: Wait + Send,
-    SPI: SpiDevice + Send,
-    DELAY: DelayNs + Send,
+    T: AsyncTransport,
+    RST: OutputPin,
+    BUSY: Wait,
+    DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> AsyncPartialUpdate<E6Color> for AsyncE6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> AsyncPartialUpdate<E6Color> for AsyncE6Display<T, RST, BUSY, DELAY, S, H>
 {
     async fn partial_update(
         &mut self,
@@ -197,93 +407,99 @@ impl<
         horizontal: RangeInclusive<u16>,
         vertical: RangeInclusive<u16>,
     ) -> Result<(), Error> {
-        let mut iter = iter.into_iter();
-        for y in vertical {
-            for x in horizontal.clone() {
-                if let Some(color) = iter.next() {
-                    self.frame_buffer
-                        .set(self.pixel_index(x as usize, y as usize), color);
-                }
-            }
-        }
+        self.core.partial_update(iter, horizontal, vertical);
         Ok(())
     }
+
+    async fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        let Some(window) = self.core.partial_window(horizontal, vertical)? else {
+            return Ok(());
+        };
+        self.run(Operation::PartialRefresh(window)).await
+    }
 }
 
 impl<
-    DC: OutputPin + Send,
-    RST: OutputPin + Send,
-    BUSY: Wait + Send,
-    SPI: SpiDevice + Send,
-    DELAY: DelayNs + Send,
+    T: AsyncTransport,
+    RST: OutputPin,
+    BUSY: Wait,
+    DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> Display<E6Color> for AsyncE6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> Display<E6Color> for AsyncE6Display<T, RST, BUSY, DELAY, S, H>
 {
     fn width(&self) -> u16 {
-        self.width
+        self.core.logical_size().0
     }
 
     fn height(&self) -> u16 {
-        self.height
+        self.core.logical_size().1
     }
 }
 
 impl<
-    DC: OutputPin + Send,
-    RST: OutputPin + Send,
-    BUSY: Wait + Send,
-    SPI: SpiDevice + Send,
-    DELAY: DelayNs + Send,
+    T: AsyncTransport,
+    RST: OutputPin,
+    BUSY: Wait,
+    DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> AsyncDisplay<E6Color> for AsyncE6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> AsyncDisplay<E6Color> for AsyncE6Display<T, RST, BUSY, DELAY, S, H>
 {
     async fn initialize(&mut self) -> Result<(), Error> {
-        info!("Initialize display");
-        self.reset().await?;
-        for (command_code, data) in INIT_SEQUENCE {
-            self.spi_write_command_and_data(*command_code, data).await?;
-        }
-        Ok(())
+        self.run(Operation::Initialize).await
     }
 
     async fn update(&mut self, iter: impl IntoIterator<Item = E6Color>) -> Result<(), Error> {
-        let mut iter = iter.into_iter();
-        for index in 0..self.frame_buffer.len() {
-            if let Some(color) = iter.next() {
-                self.frame_buffer.set(index, color);
-            }
-        }
+        self.core.update(iter);
         Ok(())
     }
 
     async fn refresh(&mut self) -> Result<(), Error> {
-        self.send_frame_buffer().await?;
-        self.refresh_display().await
+        self.core.check_frame_buffer()?;
+        self.run(Operation::Refresh).await
+    }
+
+    async fn sleep(&mut self) -> Result<(), Error> {
+        self.run(Operation::Sleep).await
+    }
+
+    async fn wake(&mut self) -> Result<(), Error> {
+        info!("Wake up from deep sleep");
+        self.run(Operation::Initialize).await
+    }
+
+    fn power_state(&self) -> PowerState {
+        self.core.power_state()
     }
 }
 
 impl<
-    DC: OutputPin + Send,
-    RST: OutputPin + Send,
-    BUSY: Wait + Send,
-    SPI: SpiDevice + Send,
-    DELAY: DelayNs + Send,
+    T: AsyncTransport,
+    RST: OutputPin,
+    BUSY: Wait,
+    DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> OriginDimensions for AsyncE6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> OriginDimensions for AsyncE6Display<T, RST, BUSY, DELAY, S, H>
 {
     fn size(&self) -> Size {
-        Size::new(self.width as u32, self.height as u32)
+        self.core.size()
     }
 }
 
 impl<
-    DC: OutputPin + Send,
-    RST: OutputPin + Send,
-    BUSY: Wait + Send,
-    SPI: SpiDevice + Send,
-    DELAY: DelayNs + Send,
+    T: AsyncTransport,
+    RST: OutputPin,
+    BUSY: Wait,
+    DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> DrawTarget for AsyncE6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> DrawTarget for AsyncE6Display<T, RST, BUSY, DELAY, S, H>
 {
     type Color = E6Color;
     type Error = Error;
@@ -292,10 +508,24 @@ impl<
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
-        for Pixel(p, c) in pixels.into_iter().take(self.frame_buffer.len()) {
-            self.frame_buffer
-                .set(self.pixel_index(p.x as usize, p.y as usize), c);
-        }
+        self.core.draw_iter(pixels);
+        Ok(())
+    }
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.core.fill_contiguous(area, colors);
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.core.fill_solid(area, color);
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.core.clear(color);
         Ok(())
     }
 }
@@ -0,0 +1,114 @@
+//! Horizontal slice of the panel for rendering without a full frame buffer.
+//!
+//! The drivers' `refresh_banded` splits the panel into bands of whole panel rows that fit into
+//! a caller-supplied buffer, runs the drawing code once per band and streams every band to the
+//! controller. Drawing code sees the whole logical display, pixels outside of the current band
+//! are dropped.
+
+use crate::display::Error;
+use crate::e6_display::E6Color;
+use crate::nibbles::Nibbles;
+use crate::orientation::Orientation;
+use core::ops::Range;
+use embedded_graphics::Pixel;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{OriginDimensions, Size};
+
+pub struct Band<'a> {
+    pixels: Nibbles<&'a mut [u8], E6Color>,
+    rows: Range<u16>,
+    width: u16,
+    height: u16,
+    orientation: Orientation,
+}
+
+impl<'a> Band<'a> {
+    /// Splits `buffer` into bands of the panel. The panel width must be even, so that bands
+    /// start on a byte boundary, and the buffer must hold at least one row.
+    pub(crate) fn new(
+        buffer: &'a mut [u8],
+        width: u16,
+        height: u16,
+        orientation: Orientation,
+    ) -> Result<Self, Error> {
+        if !width.is_multiple_of(2) {
+            return Err(Error::UnsupportedResolution { width, height });
+        }
+        let rows = (buffer.len() * 2 / width.max(1) as usize).min(height as usize);
+        if rows == 0 {
+            return Err(Error::BufferTooSmall {
+                required: width as usize,
+                len: buffer.len() * 2,
+            });
+        }
+        let len = rows * width as usize;
+        Ok(Self {
+            pixels: Nibbles::new(&mut buffer[..len / 2], len),
+            rows: 0..0,
+            width,
+            height,
+            orientation,
+        })
+    }
+
+    /// Moves to the band after the current one and fills it with `background`.
+    /// Returns `false` after the last band.
+    pub(crate) fn advance(&mut self, background: E6Color) -> bool {
+        let band_rows = (self.pixels.len() / self.width as usize) as u16;
+        let start = self.rows.end;
+        if start >= self.height {
+            return false;
+        }
+        self.rows = start..(start + band_rows).min(self.height);
+        let fill = (u8::from(background) << 4) | u8::from(background);
+        self.pixels.as_underlying_data_mut().fill(fill);
+        true
+    }
+
+    /// Starts over, the next [`Band::advance`] moves to the first band.
+    pub(crate) fn rewind(&mut self) {
+        self.rows = 0..0;
+    }
+
+    /// Packed pixels of the current band.
+    pub(crate) fn data(&self) -> &[u8] {
+        let len = self.rows.len() * self.width as usize / 2;
+        &self.pixels.as_underlying_data()[..len]
+    }
+
+    /// Panel rows covered by the band, in panel coordinates.
+    pub fn rows(&self) -> Range<u16> {
+        self.rows.clone()
+    }
+}
+
+impl OriginDimensions for Band<'_> {
+    fn size(&self) -> Size {
+        let (width, height) = self.orientation.logical_size(self.width, self.height);
+        Size::new(width as u32, height as u32)
+    }
+}
+
+impl DrawTarget for Band<'_> {
+    type Color = E6Color;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let Some((x, y)) = self
+                .orientation
+                .physical_point(point, self.width, self.height)
+            else {
+                continue;
+            };
+            if self.rows.contains(&y) {
+                let index = (y - self.rows.start) as usize * self.width as usize + x as usize;
+                self.pixels.set(index, color);
+            }
+        }
+        Ok(())
+    }
+}
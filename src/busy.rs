@@ -0,0 +1,190 @@
+use crate::display::{Error, PinRole};
+use crate::profile::{DEFAULT_BUSY_POLL_INTERVAL_MS, DEFAULT_BUSY_TIMEOUT_MS};
+use defmt::Format;
+
+/// Step of the controller lifecycle that keeps the BUSY line low.
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BusyPhase {
+    /// Controller reset, before the init sequence.
+    Reset,
+    /// Data stop after the frame buffer was sent.
+    DataTransfer,
+    PowerOn,
+    /// Display refresh, the longest phase by far.
+    Refresh,
+    PowerOff,
+}
+
+/// How long the drivers wait for the controller to release the BUSY line.
+///
+/// Every wait that exceeds its timeout fails with [`Error::BusyTimeout`] naming the phase.
+/// The blocking drivers sample the line every `poll_interval_ms`, the async drivers await the
+/// rising edge and check the timeout with the same interval. An interval of 0 counts as 1 ms.
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BusyPolicy {
+    pub poll_interval_ms: u32,
+    /// Timeout of every phase that has no timeout of its own.
+    pub timeout_ms: u32,
+    pub reset_timeout_ms: Option<u32>,
+    pub refresh_timeout_ms: Option<u32>,
+    pub power_off_timeout_ms: Option<u32>,
+}
+
+impl BusyPolicy {
+    /// Polls at least every millisecond, as the waits count time in poll intervals.
+    pub const fn new(poll_interval_ms: u32, timeout_ms: u32) -> Self {
+        Self {
+            poll_interval_ms: if poll_interval_ms == 0 {
+                1
+            } else {
+                poll_interval_ms
+            },
+            timeout_ms,
+            reset_timeout_ms: None,
+            refresh_timeout_ms: None,
+            power_off_timeout_ms: None,
+        }
+    }
+
+    pub const fn with_reset_timeout_ms(self, timeout_ms: u32) -> Self {
+        Self {
+            reset_timeout_ms: Some(timeout_ms),
+            ..self
+        }
+    }
+
+    pub const fn with_refresh_timeout_ms(self, timeout_ms: u32) -> Self {
+        Self {
+            refresh_timeout_ms: Some(timeout_ms),
+            ..self
+        }
+    }
+
+    pub const fn with_power_off_timeout_ms(self, timeout_ms: u32) -> Self {
+        Self {
+            power_off_timeout_ms: Some(timeout_ms),
+            ..self
+        }
+    }
+
+    pub const fn timeout_ms(&self, phase: BusyPhase) -> u32 {
+        let timeout_ms = match phase {
+            BusyPhase::Reset => self.reset_timeout_ms,
+            BusyPhase::Refresh => self.refresh_timeout_ms,
+            BusyPhase::PowerOff => self.power_off_timeout_ms,
+            BusyPhase::DataTransfer | BusyPhase::PowerOn => None,
+        };
+        match timeout_ms {
+            Some(timeout_ms) => timeout_ms,
+            None => self.timeout_ms,
+        }
+    }
+}
+
+impl Default for BusyPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUSY_POLL_INTERVAL_MS, DEFAULT_BUSY_TIMEOUT_MS)
+    }
+}
+
+/// User code run while the drivers wait for the BUSY line, e.g. to feed a watchdog, enter a
+/// low-power idle or update a progress indicator. A full refresh keeps the line low for tens
+/// of seconds.
+///
+/// Closures taking the phase and the milliseconds waited so far implement it.
+pub trait BusyHook {
+    /// Called at the start of every poll interval in which the BUSY line is still low, before
+    /// the driver delays.
+    /// `waited_ms` is the time already spent waiting in this phase.
+    fn on_busy(&mut self, phase: BusyPhase, waited_ms: u32);
+}
+
+/// Hook that does nothing, used unless the driver is given one.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct NoBusyHook;
+
+impl BusyHook for NoBusyHook {
+    fn on_busy(&mut self, _phase: BusyPhase, _waited_ms: u32) {}
+}
+
+impl<F: FnMut(BusyPhase, u32)> BusyHook for F {
+    fn on_busy(&mut self, phase: BusyPhase, waited_ms: u32) {
+        self(phase, waited_ms)
+    }
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn busy_wait(
+    busy_pin: &mut impl embedded_hal::digital::InputPin,
+    delay_source: &mut impl embedded_hal::delay::DelayNs,
+    busy_hook: &mut impl BusyHook,
+    policy: &BusyPolicy,
+    phase: BusyPhase,
+) -> Result<(), Error> {
+    defmt::info!("The display could be busy, waiting for {}...", phase);
+    let timeout_ms = policy.timeout_ms(phase);
+    let interval_ms = policy.poll_interval_ms.max(1);
+    let mut waited_ms = 0u32;
+    while busy_pin
+        .is_low()
+        .map_err(|err| Error::from_digital_pin_error(err).on_pin(PinRole::Busy))?
+    {
+        if waited_ms >= timeout_ms {
+            defmt::info!("The display is still busy after {} ms", waited_ms);
+            return Err(Error::BusyTimeout(phase));
+        }
+        busy_hook.on_busy(phase, waited_ms);
+        delay_source.delay_ms(interval_ms);
+        waited_ms = waited_ms.saturating_add(interval_ms);
+    }
+    defmt::info!("The display is free, continue...");
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+pub(crate) async fn async_busy_wait(
+    busy_pin: &mut impl embedded_hal_async::digital::Wait,
+    delay_source: &mut impl embedded_hal_async::delay::DelayNs,
+    busy_hook: &mut impl BusyHook,
+    policy: &BusyPolicy,
+    phase: BusyPhase,
+) -> Result<(), Error> {
+    use core::future::{Future, poll_fn};
+    use core::pin::pin;
+    use core::task::Poll;
+
+    defmt::info!("The display could be busy, waiting for {}...", phase);
+    let timeout_ms = policy.timeout_ms(phase);
+    let interval_ms = policy.poll_interval_ms.max(1);
+    let mut waited_ms = 0u32;
+    loop {
+        let mut released = pin!(busy_pin.wait_for_high());
+        // Checks the line before every interval, like the blocking wait, so the hook only runs
+        // while the panel is busy and a release at the deadline still counts.
+        let now = poll_fn(|cx| Poll::Ready(released.as_mut().poll(cx))).await;
+        let result = match now {
+            Poll::Ready(result) => Some(result),
+            Poll::Pending if waited_ms >= timeout_ms => {
+                defmt::info!("The display is still busy after {} ms", waited_ms);
+                return Err(Error::BusyTimeout(phase));
+            }
+            Poll::Pending => {
+                busy_hook.on_busy(phase, waited_ms);
+                let mut delay = pin!(delay_source.delay_ms(interval_ms));
+                poll_fn(|cx| {
+                    if let Poll::Ready(result) = released.as_mut().poll(cx) {
+                        return Poll::Ready(Some(result));
+                    }
+                    delay.as_mut().poll(cx).map(|()| None)
+                })
+                .await
+            }
+        };
+        if let Some(result) = result {
+            result.map_err(|err| Error::from_digital_pin_error(err).on_pin(PinRole::Busy))?;
+            defmt::info!("The display is free, continue...");
+            return Ok(());
+        }
+        waited_ms = waited_ms.saturating_add(interval_ms);
+    }
+}
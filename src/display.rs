@@ -1,12 +1,100 @@
+use crate::busy::BusyPhase;
+use crate::e6_display::CommandCode;
 use core::fmt::Debug;
 use core::ops::RangeInclusive;
-use defmt::Format;
+use defmt::{Debug2Format, Format, Formatter};
 use embedded_hal::{digital, spi};
 
-#[derive(Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Error {
-    SpiError(spi::ErrorKind),
-    DigitalPinError(digital::ErrorKind),
+    /// An SPI transfer failed.
+    SpiError {
+        kind: spi::ErrorKind,
+        /// Command being sent, or whose data was being sent.
+        command: Option<CommandCode>,
+        source: HalErrorText,
+    },
+    /// Setting or reading a control line failed.
+    DigitalPinError {
+        kind: digital::ErrorKind,
+        pin: Option<PinRole>,
+        /// Command being sent when the line failed.
+        command: Option<CommandCode>,
+        source: HalErrorText,
+    },
+    /// The controller kept the BUSY line low for longer than the busy policy allows.
+    BusyTimeout(BusyPhase),
+    /// An index or coordinate outside of the buffer was used.
+    OutOfBounds {
+        index: usize,
+        len: usize,
+    },
+    InvalidColor(InvalidColor),
+    /// The controller has to be initialized before this operation.
+    NotInitialized,
+    /// The frame buffer can't hold all pixels of the panel.
+    BufferTooSmall {
+        required: usize,
+        len: usize,
+    },
+    /// The driver can't split the panel with this resolution between its controllers.
+    UnsupportedResolution {
+        width: u16,
+        height: u16,
+    },
+    /// Rows of a grid can't start closer together than its width.
+    InvalidStride {
+        stride: usize,
+        width: usize,
+    },
+    /// An image has to be at least one pixel wide.
+    ZeroWidth,
+}
+
+/// Control line of the panel.
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PinRole {
+    DataCommand,
+    Reset,
+    Busy,
+}
+
+/// Controller of a panel split between two, see
+/// [`E6DualDisplay`](crate::e6_dual_display::E6DualDisplay).
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Controller {
+    /// Owns the left half of the panel and the power circuits.
+    Primary,
+    /// Owns the right half of the panel.
+    Secondary,
+}
+
+/// Value that doesn't map to any [`E6Color`](crate::e6_display::E6Color).
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InvalidColor {
+    Index(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// `Debug` output of the original HAL error.
+///
+/// It is only captured with the `hal-error-text` feature, which needs an allocator,
+/// and is empty otherwise.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct HalErrorText {
+    #[cfg(feature = "hal-error-text")]
+    text: alloc::string::String,
+}
+
+/// Power state of the panel controller as tracked by the driver.
+#[derive(Format, Copy, Clone, PartialEq, Debug)]
+pub enum PowerState {
+    /// The controller has not been reset and configured yet.
+    Uninitialized,
+    /// The controller is configured and accepts commands.
+    Awake,
+    /// The controller is in deep sleep and ignores everything until it is reset.
+    DeepSleep,
 }
 
 #[derive(Format)]
@@ -23,12 +111,21 @@ pub trait Display<C: Color> {
     fn len(&self) -> usize {
         self.width() as usize * self.height() as usize
     }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 pub trait BlockingDisplay<C: Color>: Display<C> {
     fn initialize(&mut self) -> Result<(), Error>;
     fn update(&mut self, iter: impl IntoIterator<Item = C>) -> Result<(), Error>;
     fn refresh(&mut self) -> Result<(), Error>;
+    /// Puts the controller into deep sleep. The frame buffer is kept.
+    fn sleep(&mut self) -> Result<(), Error>;
+    /// Resets the controller and runs the init sequence again.
+    fn wake(&mut self) -> Result<(), Error>;
+    fn power_state(&self) -> PowerState;
 }
 
 pub trait PartialUpdate<C: Color> {
@@ -38,15 +135,176 @@ pub trait PartialUpdate<C: Color> {
         horizontal: RangeInclusive<u16>,
         vertical: RangeInclusive<u16>,
     ) -> Result<(), Error>;
+
+    /// Sends only the given window of the frame buffer and refreshes just that region of the panel.
+    fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error>;
 }
 
 impl Error {
     pub fn from_spi_error<ERR: spi::Error>(err: ERR) -> Self {
-        Self::SpiError(err.kind())
+        Self::SpiError {
+            kind: err.kind(),
+            command: None,
+            source: HalErrorText::new(&err),
+        }
     }
 
     pub fn from_digital_pin_error<ERR: digital::Error>(err: ERR) -> Self {
-        Self::DigitalPinError(err.kind())
+        Self::DigitalPinError {
+            kind: err.kind(),
+            pin: None,
+            command: None,
+            source: HalErrorText::new(&err),
+        }
+    }
+
+    /// Records the command that was being sent, unless the error already names one.
+    pub(crate) fn during(mut self, command: CommandCode) -> Self {
+        if let Self::SpiError {
+            command: context, ..
+        }
+        | Self::DigitalPinError {
+            command: context, ..
+        } = &mut self
+        {
+            context.get_or_insert(command);
+        }
+        self
+    }
+
+    /// Records which control line failed.
+    pub(crate) fn on_pin(mut self, role: PinRole) -> Self {
+        if let Self::DigitalPinError { pin, .. } = &mut self {
+            pin.get_or_insert(role);
+        }
+        self
+    }
+
+    /// Command that was being sent when the error happened, if any.
+    pub fn command(&self) -> Option<CommandCode> {
+        match self {
+            Self::SpiError { command, .. } | Self::DigitalPinError { command, .. } => *command,
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SpiError { kind, source, .. } => write!(f, "SPI error: {kind}{source}")?,
+            Self::DigitalPinError {
+                kind, pin, source, ..
+            } => match pin {
+                Some(pin) => write!(f, "{pin:?} pin error: {kind}{source}")?,
+                None => write!(f, "pin error: {kind}{source}")?,
+            },
+            Self::BusyTimeout(phase) => write!(f, "panel stayed busy during {phase:?}")?,
+            Self::OutOfBounds { index, len } => {
+                write!(f, "index {index} is out of bounds for length {len}")?
+            }
+            Self::InvalidColor(InvalidColor::Index(index)) => {
+                write!(f, "{index} is not an E6 color index")?
+            }
+            Self::InvalidColor(InvalidColor::Rgb(r, g, b)) => {
+                write!(f, "({r}, {g}, {b}) is not an E6 palette color")?
+            }
+            Self::NotInitialized => write!(f, "the panel is not initialized")?,
+            Self::BufferTooSmall { required, len } => write!(
+                f,
+                "frame buffer holds {len} pixels, {required} are required"
+            )?,
+            Self::UnsupportedResolution { width, height } => {
+                write!(f, "unsupported resolution {width}x{height}")?
+            }
+            Self::InvalidStride { stride, width } => {
+                write!(f, "stride {stride} is less than the width {width}")?
+            }
+            Self::ZeroWidth => write!(f, "the image width is zero")?,
+        }
+        if let Some(command) = self.command() {
+            write!(f, " (while sending {command:?})")?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl Format for Error {
+    fn format(&self, f: Formatter) {
+        match self {
+            Self::SpiError { kind, command, .. } => {
+                defmt::write!(f, "SpiError({}, {})", Debug2Format(kind), command)
+            }
+            Self::DigitalPinError {
+                kind, pin, command, ..
+            } => defmt::write!(
+                f,
+                "DigitalPinError({}, {}, {})",
+                Debug2Format(kind),
+                pin,
+                command
+            ),
+            Self::BusyTimeout(phase) => defmt::write!(f, "BusyTimeout({})", phase),
+            Self::OutOfBounds { index, len } => defmt::write!(f, "OutOfBounds({}, {})", index, len),
+            Self::InvalidColor(color) => defmt::write!(f, "InvalidColor({})", color),
+            Self::NotInitialized => defmt::write!(f, "NotInitialized"),
+            Self::BufferTooSmall { required, len } => {
+                defmt::write!(f, "BufferTooSmall({}, {})", required, len)
+            }
+            Self::UnsupportedResolution { width, height } => {
+                defmt::write!(f, "UnsupportedResolution({}, {})", width, height)
+            }
+            Self::InvalidStride { stride, width } => {
+                defmt::write!(f, "InvalidStride({}, {})", stride, width)
+            }
+            Self::ZeroWidth => defmt::write!(f, "ZeroWidth"),
+        }
+    }
+}
+
+impl HalErrorText {
+    #[cfg(feature = "hal-error-text")]
+    fn new(error: &impl Debug) -> Self {
+        Self {
+            text: alloc::format!("{error:?}"),
+        }
+    }
+
+    #[cfg(not(feature = "hal-error-text"))]
+    fn new(_error: &impl Debug) -> Self {
+        Self {}
+    }
+
+    /// The captured text, `None` without the `hal-error-text` feature.
+    pub fn as_str(&self) -> Option<&str> {
+        #[cfg(feature = "hal-error-text")]
+        return Some(&self.text);
+        #[cfg(not(feature = "hal-error-text"))]
+        None
+    }
+}
+
+impl Debug for HalErrorText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.as_str() {
+            Some(text) => f.write_str(text),
+            None => f.write_str("_"),
+        }
+    }
+}
+
+impl core::fmt::Display for HalErrorText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.as_str() {
+            Some(text) => write!(f, " ({text})"),
+            None => Ok(()),
+        }
     }
 }
 
@@ -66,6 +324,11 @@ pub trait AsyncDisplay<C: Color>: Display<C> {
         iter: impl IntoIterator<Item = C>,
     ) -> impl Future<Output = Result<(), Error>>;
     fn refresh(&mut self) -> impl Future<Output = Result<(), Error>>;
+    /// Puts the controller into deep sleep. The frame buffer is kept.
+    fn sleep(&mut self) -> impl Future<Output = Result<(), Error>>;
+    /// Resets the controller and runs the init sequence again.
+    fn wake(&mut self) -> impl Future<Output = Result<(), Error>>;
+    fn power_state(&self) -> PowerState;
 }
 
 #[cfg(feature = "async")]
@@ -76,4 +339,11 @@ pub trait AsyncPartialUpdate<C: Color> {
         horizontal: RangeInclusive<u16>,
         vertical: RangeInclusive<u16>,
     ) -> impl Future<Output = Result<(), Error>>;
+
+    /// Sends only the given window of the frame buffer and refreshes just that region of the panel.
+    fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> impl Future<Output = Result<(), Error>>;
 }
@@ -0,0 +1,583 @@
+//! Frame buffer, orientation and API logic shared by the drivers.
+//!
+//! [`DisplayCore`] holds the protocol state, the frame buffer and the orientation, and
+//! implements drawing, packed updates, blitting and the windows of partial refreshes. The
+//! pixels of a refresh come from a [`Source`], which hands them out a chunk at a time. The
+//! drivers only execute the protocol steps with their transport, pins and delay source, and
+//! send the chunks of the source for every frame buffer step.
+
+use crate::band::Band;
+use crate::display::{Error, PowerState};
+use crate::e6_display::{E6Color, PartialWindow};
+use crate::nibbles::{Nibbles, underlying_data_len};
+use crate::orientation::Orientation;
+use crate::protocol::Protocol;
+use crate::sprite::Sprite;
+use core::ops::{Range, RangeInclusive};
+use embedded_graphics::Pixel;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::primitives::{PointsIter, Rectangle};
+
+/// Bytes packed per write when streaming generated pixels.
+const STREAM_CHUNK_LEN: usize = 64;
+
+pub(crate) struct DisplayCore<S: AsMut<[u8]> + AsRef<[u8]>> {
+    protocol: Protocol,
+    frame_buffer: Nibbles<S, E6Color>,
+    orientation: Orientation,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>> DisplayCore<S> {
+    /// Fails when the frame buffer can't hold the panel of the protocol.
+    pub(crate) fn try_new(
+        protocol: Protocol,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        let core = Self {
+            protocol,
+            frame_buffer,
+            orientation: Orientation::default(),
+        };
+        core.check_frame_buffer()?;
+        Ok(core)
+    }
+
+    pub(crate) fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub(crate) fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    pub(crate) fn power_state(&self) -> PowerState {
+        self.protocol.power_state()
+    }
+
+    /// The protocol, and the packed frame buffer that [`FrameBuffer`] sends.
+    pub(crate) fn protocol_and_frame(&mut self) -> (&mut Protocol, &[u8]) {
+        let len = underlying_data_len(self.frame_buffer.len());
+        (
+            &mut self.protocol,
+            &self.frame_buffer.as_underlying_data().as_ref()[..len],
+        )
+    }
+
+    /// Width and height of the panel in its native orientation.
+    fn panel_size(&self) -> (u16, u16) {
+        (self.protocol.profile.width, self.protocol.profile.height)
+    }
+
+    /// Width and height of the display as seen by drawing code.
+    pub(crate) fn logical_size(&self) -> (u16, u16) {
+        let (width, height) = self.panel_size();
+        self.orientation.logical_size(width, height)
+    }
+
+    pub(crate) fn size(&self) -> Size {
+        let (width, height) = self.logical_size();
+        Size::new(width as u32, height as u32)
+    }
+
+    /// Frame buffer index of a logical point, or `None` when it is off the panel.
+    /// Drivers without a frame buffer ignore drawing.
+    fn pixel_index(&self, point: Point) -> Option<usize> {
+        let (width, height) = self.panel_size();
+        let (x, y) = self.orientation.physical_point(point, width, height)?;
+        Some(y as usize * width as usize + x as usize)
+            .filter(|index| *index < self.frame_buffer.len())
+    }
+
+    pub(crate) fn check_frame_buffer(&self) -> Result<(), Error> {
+        let required = self.protocol.profile.len();
+        if self.frame_buffer.len() < required {
+            return Err(Error::BufferTooSmall {
+                required,
+                len: self.frame_buffer.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails on panels of odd width, whose rows don't start on a byte boundary.
+    fn check_even_width(&self) -> Result<(), Error> {
+        let (width, height) = self.panel_size();
+        if !width.is_multiple_of(2) {
+            return Err(Error::UnsupportedResolution { width, height });
+        }
+        Ok(())
+    }
+
+    /// Sets the pixels of the logical display in row-major order.
+    pub(crate) fn update(&mut self, iter: impl IntoIterator<Item = E6Color>) {
+        let (width, height) = self.logical_size();
+        let points =
+            (0..height as i32).flat_map(|y| (0..width as i32).map(move |x| Point::new(x, y)));
+        self.set_points(points, iter);
+    }
+
+    /// Sets the pixels of a logical window in row-major order.
+    pub(crate) fn partial_update(
+        &mut self,
+        iter: impl IntoIterator<Item = E6Color>,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) {
+        let points = vertical.flat_map(|y| {
+            horizontal
+                .clone()
+                .map(move |x| Point::new(x as i32, y as i32))
+        });
+        self.set_points(points, iter);
+    }
+
+    fn set_points(
+        &mut self,
+        points: impl Iterator<Item = Point>,
+        colors: impl IntoIterator<Item = E6Color>,
+    ) {
+        for (point, color) in points.zip(colors) {
+            if let Some(index) = self.pixel_index(point) {
+                self.frame_buffer.set(index, color);
+            }
+        }
+    }
+
+    /// Physical window of a partial refresh of the logical area, or `None` when nothing of
+    /// it is on the panel.
+    pub(crate) fn partial_window(
+        &self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<Option<PartialWindow>, Error> {
+        self.check_frame_buffer()?;
+        self.check_even_width()?;
+        let (width, height) = self.panel_size();
+        Ok(self
+            .orientation
+            .physical_area(horizontal, vertical, width, height)
+            .and_then(|(horizontal, vertical)| {
+                PartialWindow::new(horizontal, vertical, width, height)
+            }))
+    }
+
+    /// Replaces a window of the frame buffer, given in native panel coordinates, with packed
+    /// rows that each start on a byte boundary.
+    pub(crate) fn partial_update_packed(
+        &mut self,
+        data: &[u8],
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.check_frame_buffer()?;
+        self.check_even_width()?;
+        let (width, height) = self.panel_size();
+        if horizontal.is_empty() || vertical.is_empty() {
+            return Ok(());
+        }
+        for (end, len) in [(*horizontal.end(), width), (*vertical.end(), height)] {
+            if end >= len {
+                return Err(Error::OutOfBounds {
+                    index: end as usize,
+                    len: len as usize,
+                });
+            }
+        }
+        let columns = horizontal.len();
+        let stride = columns.div_ceil(2);
+        let required = stride * vertical.len();
+        if data.len() < required {
+            return Err(Error::BufferTooSmall {
+                required,
+                len: data.len(),
+            });
+        }
+        for (row, y) in data.chunks(stride).zip(vertical) {
+            let start = y as usize * width as usize + *horizontal.start() as usize;
+            self.frame_buffer.copy_packed(start, columns, row);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn update_packed(&mut self, data: &[u8]) -> Result<(), Error> {
+        let (width, height) = self.panel_size();
+        self.partial_update_packed(
+            data,
+            0..=width.saturating_sub(1),
+            0..=height.saturating_sub(1),
+        )
+    }
+
+    /// Draws the part of `sprite` at the logical `position` that is on the panel. Rows that
+    /// run along panel rows are copied a byte at a time where possible, the frame buffer has
+    /// to hold the whole panel.
+    pub(crate) fn blit<I: AsMut<[u8]> + AsRef<[u8]>, M: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        sprite: &Sprite<'_, I, E6Color, M>,
+        position: Point,
+    ) -> Result<(), Error> {
+        self.check_frame_buffer()?;
+        let size = Size::new(sprite.width() as u32, sprite.height() as u32);
+        let area = Rectangle::new(position, size).intersection(&self.logical_area());
+        let count = area.size.width as usize;
+        if count == 0 {
+            return Ok(());
+        }
+        let columns = area.columns();
+        for y in area.rows() {
+            let (first, last) = (
+                self.physical_index(columns.start, y),
+                self.physical_index(columns.end - 1, y),
+            );
+            let source = (
+                (columns.start - position.x) as usize,
+                (y - position.y) as usize,
+            );
+            if first + count - 1 == last {
+                sprite.blit_row(&mut self.frame_buffer, first, source, count);
+            } else {
+                let stride = (last as isize - first as isize) / (count as isize - 1);
+                for step in 0..count {
+                    if let Some(bits) = sprite.pixel_bits(source.0 + step, source.1) {
+                        self.frame_buffer
+                            .set_bits((first as isize + step as isize * stride) as usize, bits);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn draw_iter(&mut self, pixels: impl IntoIterator<Item = Pixel<E6Color>>) {
+        for Pixel(point, color) in pixels {
+            if let Some(index) = self.pixel_index(point) {
+                self.frame_buffer.set(index, color);
+            }
+        }
+    }
+
+    /// Sets the pixels of the logical `area` to `colors` in row-major order, skipping the
+    /// colors of pixels off the panel. Rows that run along panel rows are written a byte at a
+    /// time. Drivers whose frame buffer doesn't hold the whole panel draw pixel by pixel.
+    pub(crate) fn fill_contiguous(
+        &mut self,
+        area: &Rectangle,
+        colors: impl IntoIterator<Item = E6Color>,
+    ) {
+        if self.frame_buffer.len() < self.protocol.profile.len() {
+            let pixels = area.points().zip(colors);
+            self.draw_iter(pixels.map(|(point, color)| Pixel(point, color)));
+            return;
+        }
+        let mut colors = colors.into_iter();
+        let (logical_width, logical_height) = self.logical_size();
+        let columns = area.columns();
+        let start = columns.start.max(0);
+        let end = columns.end.min(logical_width as i32);
+        for y in area.rows() {
+            if y < 0 || y >= logical_height as i32 || start >= end {
+                skip(&mut colors, area.size.width as usize);
+                continue;
+            }
+            skip(&mut colors, (start - columns.start) as usize);
+            let (first, last) = (
+                self.physical_index(start, y),
+                self.physical_index(end - 1, y),
+            );
+            let count = (end - start) as usize;
+            if first + count - 1 == last {
+                self.frame_buffer.set_from(first, count, &mut colors);
+            } else {
+                let stride = (last as isize - first as isize) / (count as isize - 1);
+                for (step, color) in (0..count as isize).zip(&mut colors) {
+                    self.frame_buffer
+                        .set((first as isize + step * stride) as usize, color);
+                }
+            }
+            skip(&mut colors, (columns.end - end) as usize);
+        }
+    }
+
+    /// Fills the part of the logical `area` that is on the panel, one run of whole bytes per
+    /// panel row.
+    pub(crate) fn fill_solid(&mut self, area: &Rectangle, color: E6Color) {
+        let area = area.intersection(&self.logical_area());
+        let Some(bottom_right) = area.bottom_right() else {
+            return;
+        };
+        let (width, height) = self.panel_size();
+        let Some((horizontal, vertical)) = self.orientation.physical_area(
+            area.top_left.x as u16..=bottom_right.x as u16,
+            area.top_left.y as u16..=bottom_right.y as u16,
+            width,
+            height,
+        ) else {
+            return;
+        };
+        for y in vertical {
+            let row = y as usize * width as usize;
+            self.frame_buffer.fill(
+                row + *horizontal.start() as usize..row + *horizontal.end() as usize + 1,
+                color,
+            );
+        }
+    }
+
+    pub(crate) fn clear(&mut self, color: E6Color) {
+        self.frame_buffer
+            .fill(0..self.protocol.profile.len(), color);
+    }
+
+    /// The logical display as a rectangle.
+    fn logical_area(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size())
+    }
+
+    /// Frame buffer index of a logical point on the panel.
+    fn physical_index(&self, x: i32, y: i32) -> usize {
+        let (width, height) = self.panel_size();
+        self.orientation
+            .physical_point(Point::new(x, y), width, height)
+            .map_or(0, |(x, y)| y as usize * width as usize + x as usize)
+    }
+
+    /// Source that sends the frame buffer.
+    pub(crate) fn frame_buffer(&self) -> FrameBuffer {
+        let (width, height) = self.panel_size();
+        let window = PartialWindow::full(width, height);
+        FrameBuffer {
+            width,
+            height,
+            window,
+            rows: window.vertical(),
+            whole: false,
+        }
+    }
+
+    /// Source that computes every pixel with `pixel`, which is called once per logical point
+    /// of a window, in the panel's scan order.
+    pub(crate) fn pixels<F: FnMut(Point) -> E6Color>(&self, pixel: F) -> Pixels<F> {
+        let (width, height) = self.panel_size();
+        Pixels {
+            pixel,
+            orientation: self.orientation,
+            width,
+            height,
+            window: PartialWindow::full(width, height),
+            next: 0,
+            chunk: [0; STREAM_CHUNK_LEN],
+        }
+    }
+
+    /// Source that fills the rows of a window with `fill_row`, in panel coordinates.
+    /// `row_buffer` needs half a byte per pixel of a panel row, and the panel width must be
+    /// even.
+    pub(crate) fn rows<'a, F: FnMut(u16, &mut Nibbles<&mut [u8], E6Color>)>(
+        &self,
+        row_buffer: &'a mut [u8],
+        fill_row: F,
+    ) -> Result<Rows<'a, F>, Error> {
+        self.check_even_width()?;
+        let (width, height) = self.panel_size();
+        let window = PartialWindow::full(width, height);
+        Ok(Rows {
+            row: Nibbles::try_new(row_buffer, width as usize)?,
+            fill_row,
+            window,
+            rows: window.vertical(),
+        })
+    }
+
+    /// Source that renders bands of the panel into `band_buffer` with `draw`, after filling
+    /// them with `background`. The buffer must hold at least one panel row.
+    pub(crate) fn bands<'a, F: FnMut(&mut Band<'_>) -> Result<(), Error>>(
+        &self,
+        band_buffer: &'a mut [u8],
+        background: E6Color,
+        draw: F,
+    ) -> Result<Bands<'a, F>, Error> {
+        let (width, height) = self.panel_size();
+        Ok(Bands {
+            band: Band::new(band_buffer, width, height, self.orientation)?,
+            background,
+            draw,
+            width,
+            height,
+            window: PartialWindow::full(width, height),
+            rows: 0..0,
+        })
+    }
+}
+
+impl DisplayCore<[u8; 0]> {
+    /// Core that keeps no image, drawing to it does nothing.
+    pub(crate) fn without_frame_buffer(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            frame_buffer: Nibbles::new([], 0),
+            orientation: Orientation::default(),
+        }
+    }
+}
+
+/// Drops the next `count` colors.
+fn skip(colors: &mut impl Iterator<Item = E6Color>, count: usize) {
+    if count > 0 {
+        colors.nth(count - 1);
+    }
+}
+
+/// Pixels of the windows of an operation, handed out in chunks of packed bytes.
+pub(crate) trait Source {
+    /// Starts over with the next window, in panel coordinates.
+    fn start(&mut self, window: PartialWindow);
+
+    /// Next chunk of the window, or `None` once all of it has been handed out. `frame_buffer`
+    /// is the packed frame buffer of the driver.
+    fn next_chunk<'a>(&'a mut self, frame_buffer: &'a [u8]) -> Result<Option<&'a [u8]>, Error>;
+}
+
+/// Sends the frame buffer: the whole buffer in one chunk for a full window, the rows of the
+/// window otherwise.
+pub(crate) struct FrameBuffer {
+    width: u16,
+    height: u16,
+    window: PartialWindow,
+    rows: RangeInclusive<u16>,
+    whole: bool,
+}
+
+impl Source for FrameBuffer {
+    fn start(&mut self, window: PartialWindow) {
+        self.window = window;
+        self.whole = window == PartialWindow::full(self.width, self.height);
+        self.rows = if self.whole { 0..=0 } else { window.vertical() };
+    }
+
+    fn next_chunk<'a>(&'a mut self, frame_buffer: &'a [u8]) -> Result<Option<&'a [u8]>, Error> {
+        Ok(self.rows.next().map(|y| {
+            if self.whole {
+                frame_buffer
+            } else {
+                self.window.row(frame_buffer, self.width, y)
+            }
+        }))
+    }
+}
+
+/// Packs pixels computed on the fly, see [`DisplayCore::pixels`].
+pub(crate) struct Pixels<F> {
+    pixel: F,
+    orientation: Orientation,
+    width: u16,
+    height: u16,
+    window: PartialWindow,
+    /// Index of the next pixel in the window.
+    next: usize,
+    chunk: [u8; STREAM_CHUNK_LEN],
+}
+
+impl<F: FnMut(Point) -> E6Color> Source for Pixels<F> {
+    fn start(&mut self, window: PartialWindow) {
+        self.window = window;
+        self.next = 0;
+    }
+
+    fn next_chunk<'a>(&'a mut self, _: &'a [u8]) -> Result<Option<&'a [u8]>, Error> {
+        let (horizontal, vertical) = (self.window.horizontal(), self.window.vertical());
+        let columns = horizontal.len();
+        let count = (columns * vertical.len() - self.next).min(STREAM_CHUNK_LEN * 2);
+        if count == 0 {
+            return Ok(None);
+        }
+        let (width, height, orientation) = (self.width, self.height, self.orientation);
+        let pixel = &mut self.pixel;
+        let mut pixels = (self.next..self.next + count).map(|index| {
+            let x = *horizontal.start() + (index % columns) as u16;
+            let y = *vertical.start() + (index / columns) as u16;
+            pixel(orientation.logical_point(x, y, width, height))
+        });
+        let len = pack_pixels(&mut pixels, &mut self.chunk);
+        self.next += count;
+        Ok(Some(&self.chunk[..len]))
+    }
+}
+
+/// Packs pixels into `buffer` in the frame buffer layout, the first pixel of a pair in the
+/// high nibble, until either runs out. Returns the number of bytes filled.
+fn pack_pixels(pixels: &mut impl Iterator<Item = E6Color>, buffer: &mut [u8]) -> usize {
+    for (len, byte) in buffer.iter_mut().enumerate() {
+        let Some(high) = pixels.next() else {
+            return len;
+        };
+        let low = pixels.next().map_or(0, u8::from);
+        *byte = (u8::from(high) << 4) | low;
+    }
+    buffer.len()
+}
+
+/// Fills one panel row at a time, see [`DisplayCore::rows`].
+pub(crate) struct Rows<'a, F> {
+    row: Nibbles<&'a mut [u8], E6Color>,
+    fill_row: F,
+    window: PartialWindow,
+    rows: RangeInclusive<u16>,
+}
+
+impl<F: FnMut(u16, &mut Nibbles<&mut [u8], E6Color>)> Source for Rows<'_, F> {
+    fn start(&mut self, window: PartialWindow) {
+        self.window = window;
+        self.rows = window.vertical();
+    }
+
+    fn next_chunk<'a>(&'a mut self, _: &'a [u8]) -> Result<Option<&'a [u8]>, Error> {
+        let Some(y) = self.rows.next() else {
+            return Ok(None);
+        };
+        (self.fill_row)(y, &mut self.row);
+        Ok(Some(
+            &self.row.as_underlying_data()[self.window.row_bytes()],
+        ))
+    }
+}
+
+/// Renders the panel band by band, see [`DisplayCore::bands`].
+pub(crate) struct Bands<'a, F> {
+    band: Band<'a>,
+    background: E6Color,
+    draw: F,
+    width: u16,
+    height: u16,
+    window: PartialWindow,
+    /// Rows of the current band still to send, all at once for a full window.
+    rows: Range<usize>,
+}
+
+impl<F: FnMut(&mut Band<'_>) -> Result<(), Error>> Source for Bands<'_, F> {
+    fn start(&mut self, window: PartialWindow) {
+        self.window = window;
+        self.band.rewind();
+        self.rows = 0..0;
+    }
+
+    fn next_chunk<'a>(&'a mut self, _: &'a [u8]) -> Result<Option<&'a [u8]>, Error> {
+        let whole = self.window == PartialWindow::full(self.width, self.height);
+        if self.rows.is_empty() {
+            if !self.band.advance(self.background) {
+                return Ok(None);
+            }
+            (self.draw)(&mut self.band)?;
+            self.rows = 0..if whole { 1 } else { self.band.rows().len() };
+        }
+        let row = self.rows.start;
+        self.rows.start += 1;
+        let data = self.band.data();
+        if whole {
+            return Ok(Some(data));
+        }
+        let stride = self.width as usize / 2;
+        Ok(Some(
+            &data[row * stride..(row + 1) * stride][self.window.row_bytes()],
+        ))
+    }
+}
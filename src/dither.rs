@@ -0,0 +1,278 @@
+//! Dithering of true-color images into the six colors of the panel.
+//!
+//! [`Ditherer`] takes the pixels of an image in row-major order, one at a time, so images can
+//! be converted while they are decoded or streamed. Error diffusion keeps the diffused error of
+//! a few rows in a caller-supplied buffer, see [`DitherMethod::error_buffer_len`]. Ordered
+//! dithering needs no memory at all.
+//!
+//! [`Ditherer::dither`] turns an iterator of [`Rgb888`] pixels into one of [`E6Color`]s, which
+//! can be passed to `update` or `partial_update` of the drivers directly.
+
+use crate::display::Error;
+use crate::e6_display::E6Color;
+use crate::palette::Palette;
+use defmt::Format;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::RgbColor;
+
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DitherMethod {
+    /// Error diffusion to 4 neighbours, the classic choice for photos.
+    FloydSteinberg,
+    /// Error diffusion that drops a quarter of the error, for higher contrast.
+    Atkinson,
+    /// Error diffusion to 3 neighbours, cheap and close to Floyd-Steinberg.
+    SierraLite,
+    /// Ordered dithering with an 8x8 Bayer matrix.
+    Bayer,
+    /// Ordered dithering with a 16x16 blue-noise matrix, without the Bayer cross-hatch.
+    BlueNoise,
+}
+
+impl DitherMethod {
+    /// Rows of diffused error the method keeps while dithering.
+    pub const fn error_rows(&self) -> usize {
+        match self {
+            DitherMethod::FloydSteinberg | DitherMethod::SierraLite => 2,
+            DitherMethod::Atkinson => 3,
+            DitherMethod::Bayer | DitherMethod::BlueNoise => 0,
+        }
+    }
+
+    /// Entries of the error buffer needed for images of the given width.
+    pub const fn error_buffer_len(&self, width: usize) -> usize {
+        self.error_rows() * width
+    }
+
+    /// Neighbours receiving the error as (dx, dy, weight), and the sum the weights divide by.
+    const fn kernel(&self) -> (&'static [(i8, u8, i16)], i16) {
+        match self {
+            DitherMethod::FloydSteinberg => (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16),
+            DitherMethod::Atkinson => (
+                &[
+                    (1, 0, 1),
+                    (2, 0, 1),
+                    (-1, 1, 1),
+                    (0, 1, 1),
+                    (1, 1, 1),
+                    (0, 2, 1),
+                ],
+                8,
+            ),
+            DitherMethod::SierraLite => (&[(1, 0, 2), (-1, 1, 1), (0, 1, 1)], 4),
+            DitherMethod::Bayer | DitherMethod::BlueNoise => (&[], 1),
+        }
+    }
+}
+
+/// Diffused error of the red, green and blue channel.
+pub type ColorError = [i16; 3];
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Ranks of a void-and-cluster blue-noise pattern.
+const BLUE_NOISE: [[u8; 16]; 16] = [
+    [
+        234, 50, 188, 19, 58, 171, 121, 47, 163, 1, 247, 104, 22, 132, 14, 65,
+    ],
+    [
+        209, 8, 118, 97, 240, 205, 23, 228, 138, 64, 123, 170, 72, 224, 99, 149,
+    ],
+    [
+        85, 139, 229, 165, 78, 146, 111, 84, 176, 216, 30, 231, 153, 201, 42, 180,
+    ],
+    [
+        25, 62, 195, 29, 43, 185, 7, 249, 41, 100, 191, 48, 87, 5, 128, 243,
+    ],
+    [
+        221, 152, 101, 253, 130, 220, 59, 200, 156, 12, 136, 112, 254, 174, 69, 109,
+    ],
+    [
+        46, 189, 0, 73, 172, 90, 142, 116, 80, 237, 210, 61, 147, 33, 206, 160,
+    ],
+    [
+        81, 124, 217, 113, 208, 15, 241, 27, 168, 45, 178, 20, 193, 96, 225, 18,
+    ],
+    [
+        242, 164, 60, 35, 157, 53, 181, 68, 223, 105, 125, 83, 236, 131, 55, 141,
+    ],
+    [
+        197, 10, 227, 134, 246, 95, 126, 198, 148, 3, 244, 161, 71, 9, 182, 106,
+    ],
+    [
+        40, 93, 179, 75, 192, 6, 218, 36, 91, 57, 202, 34, 215, 155, 233, 74,
+    ],
+    [
+        252, 120, 150, 24, 110, 63, 166, 119, 232, 183, 133, 103, 49, 117, 31, 167,
+    ],
+    [
+        16, 212, 51, 238, 207, 137, 255, 21, 76, 151, 13, 250, 190, 88, 203, 135,
+    ],
+    [
+        102, 184, 82, 169, 38, 89, 187, 52, 204, 98, 173, 67, 129, 4, 222, 56,
+    ],
+    [
+        230, 144, 2, 127, 226, 11, 154, 114, 239, 39, 219, 28, 235, 145, 175, 77,
+    ],
+    [
+        196, 37, 248, 70, 107, 199, 66, 177, 17, 143, 115, 159, 86, 44, 108, 26,
+    ],
+    [
+        122, 92, 158, 214, 140, 32, 245, 94, 213, 79, 194, 54, 211, 186, 251, 162,
+    ],
+];
+
+/// Converts pixels of an image into panel colors, in row-major order.
+pub struct Ditherer<'a> {
+    method: DitherMethod,
+    width: usize,
+    errors: &'a mut [ColorError],
+    palette: Palette,
+    x: usize,
+    y: usize,
+}
+
+impl<'a> Ditherer<'a> {
+    /// `errors` needs [`DitherMethod::error_buffer_len`] entries and can be empty for ordered
+    /// dithering. Fails with [`Error::ZeroWidth`] for an image without columns.
+    pub fn new(
+        method: DitherMethod,
+        width: usize,
+        errors: &'a mut [ColorError],
+    ) -> Result<Self, Error> {
+        if width == 0 {
+            return Err(Error::ZeroWidth);
+        }
+        let required = method.error_buffer_len(width);
+        if errors.len() < required {
+            return Err(Error::BufferTooSmall {
+                required,
+                len: errors.len(),
+            });
+        }
+        let errors = &mut errors[..required];
+        errors.fill([0; 3]);
+        Ok(Self {
+            method,
+            width,
+            errors,
+            palette: Palette::DEFAULT,
+            x: 0,
+            y: 0,
+        })
+    }
+
+    /// Matches the pixels against `palette` instead of the default palette.
+    pub fn with_palette(self, palette: Palette) -> Self {
+        Self { palette, ..self }
+    }
+
+    /// Panel color of the next pixel of the image.
+    pub fn dither_pixel(&mut self, color: Rgb888) -> E6Color {
+        let rgb = [color.r() as i16, color.g() as i16, color.b() as i16];
+        let color = match self.method {
+            DitherMethod::Bayer => {
+                let rank = BAYER[self.y % 8][self.x % 8];
+                nearest(&self.palette, offset(rgb, rank as i16, 64)).0
+            }
+            DitherMethod::BlueNoise => {
+                let rank = BLUE_NOISE[self.y % 16][self.x % 16];
+                nearest(&self.palette, offset(rgb, rank as i16, 256)).0
+            }
+            _ => self.diffuse(rgb),
+        };
+        self.x += 1;
+        if self.x == self.width {
+            self.x = 0;
+            self.y += 1;
+        }
+        color
+    }
+
+    /// Panel colors of the following pixels of the image.
+    pub fn dither<I: IntoIterator<Item = Rgb888>>(self, pixels: I) -> Dithered<'a, I::IntoIter> {
+        Dithered {
+            ditherer: self,
+            pixels: pixels.into_iter(),
+        }
+    }
+
+    fn diffuse(&mut self, rgb: ColorError) -> E6Color {
+        let rows = self.method.error_rows();
+        let slot = (self.y % rows) * self.width + self.x;
+        // The slot is free for the pixel of the same column `rows` rows below.
+        let error = core::mem::take(&mut self.errors[slot]);
+        let wanted: ColorError =
+            core::array::from_fn(|channel| (rgb[channel] + error[channel]).clamp(0, 255));
+        let (color, actual) = nearest(&self.palette, wanted);
+        let error: ColorError = core::array::from_fn(|channel| wanted[channel] - actual[channel]);
+        let (kernel, divisor) = self.method.kernel();
+        for (dx, dy, weight) in kernel {
+            let Some(x) = self.x.checked_add_signed(*dx as isize) else {
+                continue;
+            };
+            if x >= self.width {
+                continue;
+            }
+            let target = &mut self.errors[((self.y + *dy as usize) % rows) * self.width + x];
+            for channel in 0..3 {
+                target[channel] += error[channel] * weight / divisor;
+            }
+        }
+        color
+    }
+}
+
+/// Iterator over dithered pixels, see [`Ditherer::dither`].
+pub struct Dithered<'a, I> {
+    ditherer: Ditherer<'a>,
+    pixels: I,
+}
+
+impl<I: Iterator<Item = Rgb888>> Iterator for Dithered<'_, I> {
+    type Item = E6Color;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let color = self.pixels.next()?;
+        Some(self.ditherer.dither_pixel(color))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pixels.size_hint()
+    }
+}
+
+/// Shifts every channel by the threshold of the rank, spread over the whole channel range.
+fn offset(rgb: ColorError, rank: i16, ranks: i16) -> ColorError {
+    let offset = ((2 * rank as i32 + 1 - ranks as i32) * 255 / (2 * ranks as i32)) as i16;
+    rgb.map(|channel| channel + offset)
+}
+
+/// Palette color closest to the given one, and its RGB value.
+fn nearest(palette: &Palette, rgb: ColorError) -> (E6Color, ColorError) {
+    let mut best = (E6Color::Black, [0; 3]);
+    let mut best_distance = i32::MAX;
+    for (color, (r, g, b)) in palette.entries() {
+        let palette_rgb = [r as i16, g as i16, b as i16];
+        let distance = (0..3)
+            .map(|channel| {
+                let difference = (rgb[channel] - palette_rgb[channel]) as i32;
+                difference * difference
+            })
+            .sum();
+        if distance < best_distance {
+            best = (color, palette_rgb);
+            best_distance = distance;
+        }
+    }
+    best
+}
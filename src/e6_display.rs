@@ -1,67 +1,67 @@
+use crate::busy::{BusyHook, NoBusyHook};
 pub use crate::display::RgbColor as DisplayRgbColor;
-pub use crate::display::{AsRgbColor, BlockingDisplay, Color, Error, PartialUpdate};
-use crate::nibbles::Nibbles;
-use core::time::Duration;
+pub use crate::display::{AsRgbColor, BlockingDisplay, Color, Error, PartialUpdate, PowerState};
+use crate::display::{InvalidColor, PinRole};
+use crate::display_core::DisplayCore;
+use crate::palette::Palette;
+use crate::transport::Transport;
+use core::ops::RangeInclusive;
 use defmt::Format;
+use embedded_graphics::pixelcolor::raw::{RawData, RawU4};
 use embedded_graphics::pixelcolor::{BinaryColor, Rgb888};
-use embedded_graphics::prelude::{PixelColor, RgbColor};
+use embedded_graphics::prelude::PixelColor;
 use embedded_hal::delay::DelayNs;
 use embedded_hal::digital::{InputPin, OutputPin, PinState};
-use embedded_hal::spi::SpiDevice;
 
+#[cfg(feature = "blocking")]
+use crate::band::Band;
+#[cfg(feature = "blocking")]
+use crate::busy::busy_wait;
+#[cfg(feature = "blocking")]
+use crate::display::Controller;
 #[cfg(feature = "blocking")]
 pub(crate) use crate::display::Display;
 #[cfg(feature = "blocking")]
-use core::ops::{RangeInclusive, SubAssign};
+use crate::display_core::Source;
+#[cfg(feature = "blocking")]
+use crate::nibbles::Nibbles;
+#[cfg(feature = "blocking")]
+use crate::orientation::Orientation;
+#[cfg(feature = "blocking")]
+use crate::profile::PanelProfile;
+#[cfg(feature = "blocking")]
+use crate::protocol::{Operation, Protocol, Step};
+#[cfg(feature = "blocking")]
+use crate::sprite::Sprite;
+#[cfg(feature = "blocking")]
+use crate::transport::FourWire;
 #[cfg(feature = "blocking")]
 use embedded_graphics::Pixel;
 #[cfg(feature = "blocking")]
-use embedded_graphics::geometry::Size;
+use embedded_graphics::geometry::{Point, Size};
 #[cfg(feature = "blocking")]
 use embedded_graphics::prelude::{DrawTarget, OriginDimensions};
-
-pub(crate) const RESET_DELAY_MS: u32 = 30;
-pub(crate) const BUSY_WAIT_DELAY_MS: u32 = 100;
-pub(crate) const BUSY_WAIT_TIMEOUT_MS: Duration = Duration::from_millis(20_000);
-
-pub(crate) const INIT_SEQUENCE: &[(CommandCode, &[u8])] = &[
-    (CommandCode::INIT, &[0x49, 0x55, 0x20, 0x08, 0x09, 0x18]),
-    (CommandCode::PWR, &[0x3F]),
-    (CommandCode::PSR, &[0x5F, 0x69]),
-    (CommandCode::BTST1, &[0x40, 0x1F, 0x1F, 0x2C]),
-    (CommandCode::BTST3, &[0x6F, 0x1F, 0x1F, 0x22]),
-    (CommandCode::BTST2, &[0x6F, 0x1F, 0x17, 0x17]),
-    (CommandCode::POFS, &[0x00, 0x54, 0x00, 0x44]),
-    (CommandCode::TCON, &[0x02, 0x00]),
-    (CommandCode::PLL, &[0x08]),
-    (CommandCode::CDI, &[0x3F]),
-    (CommandCode::TRES, &[0x03, 0x20, 0x01, 0xE0]),
-    (CommandCode::PWS, &[0x2F]),
-    (CommandCode::VDCS, &[0x01]),
-];
+#[cfg(feature = "blocking")]
+use embedded_graphics::primitives::Rectangle;
+#[cfg(feature = "blocking")]
+use embedded_hal::spi::SpiDevice;
 
 pub struct E6Display<
-    DC: OutputPin,
+    T: Transport,
     RST: OutputPin,
     BUSY: InputPin,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook = NoBusyHook,
 > {
-    spi: SPI,
-    dc_pin: DC,
-    rst_pin: RST,
-    busy_pin: BUSY,
-    width: u16,
-    height: u16,
-    delay_source: DELAY,
-    frame_buffer: Nibbles<S, E6Color>,
+    executor: BlockingExecutor<T, RST, BUSY, DELAY, H>,
+    core: DisplayCore<S>,
 }
 
 #[repr(u8)]
-#[derive(Format, Copy, Clone)]
-#[allow(dead_code)]
-pub(crate) enum CommandCode {
+#[derive(Format, Copy, Clone, PartialEq, Debug)]
+#[allow(dead_code, clippy::upper_case_acronyms, non_camel_case_types)]
+pub enum CommandCode {
     PSR = 0x00,
     PWR = 0x01,
     POF = 0x02,
@@ -83,13 +83,246 @@ pub(crate) enum CommandCode {
     PTL = 0x83,
     PWS = 0xE3,
     INIT = 0xAA,
+    AN_TM = 0x74,
+    AGID = 0x86,
+    BUCK_BOOST_VDDN = 0xB0,
+    TFT_VCOM_POWER = 0xB1,
+    EN_BUF = 0xB6,
+    BOOST_VDDP_EN = 0xB7,
+    CCSET = 0xE0,
+    CMD66 = 0xF0,
 }
 
+#[derive(Copy, Clone)]
 pub(crate) enum DataCommand {
     Data,
     Command,
 }
 
+/// Partial window in panel coordinates, widened to whole bytes of the frame buffer.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct PartialWindow {
+    x_start: u16,
+    x_end: u16,
+    y_start: u16,
+    y_end: u16,
+}
+
+impl PartialWindow {
+    /// Clamps the ranges to the panel and aligns the horizontal range to pixel pairs.
+    /// Returns `None` when a range is empty or reversed, or nothing of the window is on the
+    /// panel.
+    pub(crate) fn new(
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+        width: u16,
+        height: u16,
+    ) -> Option<Self> {
+        if width == 0 || height == 0 || horizontal.is_empty() || vertical.is_empty() {
+            return None;
+        }
+        let x_start = *horizontal.start() & !1;
+        let x_end = (*horizontal.end() | 1).min(width - 1);
+        let y_start = *vertical.start();
+        let y_end = (*vertical.end()).min(height - 1);
+        (x_start <= x_end && y_start <= y_end).then_some(Self {
+            x_start,
+            x_end,
+            y_start,
+            y_end,
+        })
+    }
+
+    pub(crate) fn full(width: u16, height: u16) -> Self {
+        Self {
+            x_start: 0,
+            x_end: width.saturating_sub(1),
+            y_start: 0,
+            y_end: height.saturating_sub(1),
+        }
+    }
+
+    /// Part of the window within the given columns, or `None` when they don't overlap.
+    pub(crate) fn clip_columns(&self, start: u16, end: u16) -> Option<Self> {
+        let x_start = self.x_start.max(start);
+        let x_end = self.x_end.min(end);
+        (x_start <= x_end).then_some(Self {
+            x_start,
+            x_end,
+            ..*self
+        })
+    }
+
+    /// The same window moved `offset` columns to the left.
+    pub(crate) fn shift_left(&self, offset: u16) -> Self {
+        Self {
+            x_start: self.x_start - offset,
+            x_end: self.x_end - offset,
+            ..*self
+        }
+    }
+
+    pub(crate) fn horizontal(&self) -> RangeInclusive<u16> {
+        self.x_start..=self.x_end
+    }
+
+    pub(crate) fn vertical(&self) -> RangeInclusive<u16> {
+        self.y_start..=self.y_end
+    }
+
+    /// Bytes of a packed panel row within the window, for panels of even width.
+    pub(crate) fn row_bytes(&self) -> RangeInclusive<usize> {
+        self.x_start as usize / 2..=self.x_end as usize / 2
+    }
+
+    /// PTL payload: horizontal and vertical start/end followed by PT_SCAN, which limits
+    /// gate scanning to the inside of the window.
+    pub(crate) fn command_data(&self) -> [u8; 9] {
+        let [x_start_high, x_start_low] = self.x_start.to_be_bytes();
+        let [x_end_high, x_end_low] = self.x_end.to_be_bytes();
+        let [y_start_high, y_start_low] = self.y_start.to_be_bytes();
+        let [y_end_high, y_end_low] = self.y_end.to_be_bytes();
+        [
+            x_start_high,
+            x_start_low,
+            x_end_high,
+            x_end_low,
+            y_start_high,
+            y_start_low,
+            y_end_high,
+            y_end_low,
+            0x01,
+        ]
+    }
+
+    /// Packed frame buffer bytes of the window in panel row `y`. The frame buffer width must
+    /// be even, so that every row starts on a byte boundary.
+    pub(crate) fn row<'a>(&self, frame_buffer: &'a [u8], width: u16, y: u16) -> &'a [u8] {
+        let row = y as usize * width as usize;
+        &frame_buffer[(row + self.x_start as usize) / 2..=(row + self.x_end as usize) / 2]
+    }
+}
+
+/// Transport, control lines and delay source of a blocking driver, which execute the steps of
+/// the protocol.
+pub(crate) struct BlockingExecutor<T, RST, BUSY, DELAY, H> {
+    transport: T,
+    rst_pin: RST,
+    busy_pin: BUSY,
+    delay_source: DELAY,
+    busy_hook: H,
+}
+
+#[cfg(feature = "blocking")]
+impl<T, RST, BUSY, DELAY> BlockingExecutor<T, RST, BUSY, DELAY, NoBusyHook> {
+    pub(crate) fn new(transport: T, rst_pin: RST, busy_pin: BUSY, delay_source: DELAY) -> Self {
+        Self {
+            transport,
+            rst_pin,
+            busy_pin,
+            delay_source,
+            busy_hook: NoBusyHook,
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<T, RST, BUSY, DELAY, H> BlockingExecutor<T, RST, BUSY, DELAY, H> {
+    pub(crate) fn with_busy_hook<HOOK>(
+        self,
+        busy_hook: HOOK,
+    ) -> BlockingExecutor<T, RST, BUSY, DELAY, HOOK> {
+        BlockingExecutor {
+            transport: self.transport,
+            rst_pin: self.rst_pin,
+            busy_pin: self.busy_pin,
+            delay_source: self.delay_source,
+            busy_hook,
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<T: Transport, RST: OutputPin, BUSY: InputPin, DELAY: DelayNs, H: BusyHook>
+    BlockingExecutor<T, RST, BUSY, DELAY, H>
+{
+    /// Executes the steps of the operation, sending the windows from `source`. `select`
+    /// directs the transport to a controller of a panel that has two.
+    pub(crate) fn run<S: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        core: &mut DisplayCore<S>,
+        operation: Operation,
+        source: &mut impl Source,
+        mut select: impl FnMut(&mut T, Controller),
+    ) -> Result<(), Error> {
+        let (protocol, frame_buffer) = core.protocol_and_frame();
+        let busy_policy = protocol.profile.busy_policy;
+        let mut sequence = protocol.sequence(operation)?;
+        let mut command = None;
+        while let Some(step) = sequence.next() {
+            let result = match step {
+                Step::Reset(active) => self
+                    .rst_pin
+                    .set_state(PinState::from(!active))
+                    .map_err(|err| Error::from_digital_pin_error(err).on_pin(PinRole::Reset)),
+                Step::DelayMs(ms) => {
+                    self.delay_source.delay_ms(ms);
+                    Ok(())
+                }
+                Step::Select(controller) => {
+                    select(&mut self.transport, controller);
+                    Ok(())
+                }
+                Step::Command(code) => {
+                    command = Some(code);
+                    self.transport.write_command(code)
+                }
+                Step::Data(data) => {
+                    defmt::info!("Sending data chunk: {}", data.len());
+                    self.transport.write_data(data)
+                }
+                Step::CommandWithStatus(code) => {
+                    command = Some(code);
+                    let mut status = [0u8; 1];
+                    self.transport
+                        .write_command_with_status(code, &mut status)
+                        .inspect(|()| defmt::info!("Command {} status: {}", code, status))
+                }
+                Step::FrameBuffer(window) => {
+                    defmt::info!("Sending window: {}", window.command_data());
+                    source.start(window);
+                    send(&mut self.transport, source, frame_buffer)
+                }
+                Step::WaitBusy(phase) => busy_wait(
+                    &mut self.busy_pin,
+                    &mut self.delay_source,
+                    &mut self.busy_hook,
+                    &busy_policy,
+                    phase,
+                ),
+            };
+            result.map_err(|error| match command {
+                Some(command) => error.during(command),
+                None => error,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the chunks of the current window of `source`.
+#[cfg(feature = "blocking")]
+fn send(
+    transport: &mut impl Transport,
+    source: &mut impl Source,
+    frame_buffer: &[u8],
+) -> Result<(), Error> {
+    while let Some(chunk) = source.next_chunk(frame_buffer)? {
+        transport.write_data(chunk)?;
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 #[cfg(feature = "blocking")]
 impl<
@@ -99,8 +332,10 @@ impl<
     SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> E6Display<DC, RST, BUSY, SPI, DELAY, S>
+> E6Display<FourWire<SPI, DC>, RST, BUSY, DELAY, S>
 {
+    /// Creates a driver for a 7.3" compatible panel with the given resolution.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         width: u16,
         height: u16,
@@ -111,206 +346,294 @@ impl<
         delay_source: DELAY,
         frame_buffer: Nibbles<S, E6Color>,
     ) -> Self {
-        assert!(
-            frame_buffer.len() >= width as usize * height as usize,
-            "Frame Buffer has not enough space for all pixels"
-        );
-        Self {
+        Self::with_profile(
+            PanelProfile::SPECTRA6_7IN3.with_resolution(width, height),
             spi,
             dc_pin,
             rst_pin,
             busy_pin,
-            width,
-            height,
             delay_source,
             frame_buffer,
-        }
+        )
     }
 
-    fn power_off(&mut self) -> Result<(), Error> {
-        self.spi_write_command(CommandCode::POF)
+    pub fn with_profile(
+        profile: PanelProfile,
+        spi: SPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        Self::with_transport(
+            profile,
+            FourWire::new(spi, dc_pin),
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
     }
 
-    fn power_on(&mut self) -> Result<(), Error> {
-        self.spi_write_command(CommandCode::PON)
+    pub fn try_with_profile(
+        profile: PanelProfile,
+        spi: SPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        Self::try_with_transport(
+            profile,
+            FourWire::new(spi, dc_pin),
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
     }
+}
 
-    fn display_refresh(&mut self) -> Result<(), Error> {
-        self.spi_write_command_and_data(CommandCode::DRF, &[0x00])
+#[allow(dead_code)]
+#[cfg(feature = "blocking")]
+impl<T: Transport, RST: OutputPin, BUSY: InputPin, DELAY: DelayNs, S: AsMut<[u8]> + AsRef<[u8]>>
+    E6Display<T, RST, BUSY, DELAY, S>
+{
+    /// Creates a driver that talks to the controller through the given transport, e.g.
+    /// [`ThreeWire`](crate::transport::ThreeWire).
+    pub fn with_transport(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        match Self::try_with_transport(
+            profile,
+            transport,
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        ) {
+            Ok(display) => display,
+            Err(error) => panic!("{}", error),
+        }
     }
 
-    fn set_data_command(&mut self, data_command: DataCommand) -> Result<(), Error> {
-        set_data_command(&mut self.dc_pin, data_command)
+    pub fn try_with_transport(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            executor: BlockingExecutor::new(transport, rst_pin, busy_pin, delay_source),
+            core: DisplayCore::try_new(Protocol::new(profile), frame_buffer)?,
+        })
     }
+}
 
-    fn spi_write_command(&mut self, command: CommandCode) -> Result<(), Error> {
-        self.set_data_command(DataCommand::Command)?;
-        self.spi
-            .write(&[command as u8])
-            .map_err(Error::from_spi_error)
+#[cfg(feature = "blocking")]
+impl<T: Transport, RST: OutputPin, BUSY: InputPin, DELAY: DelayNs>
+    E6Display<T, RST, BUSY, DELAY, [u8; 0]>
+{
+    /// Creates a driver that keeps no image. Drawing to it does nothing, and the panel is
+    /// refreshed with [`E6Display::refresh_from_fn`] or [`E6Display::refresh_from_rows`].
+    pub fn without_frame_buffer(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+    ) -> Self {
+        Self {
+            executor: BlockingExecutor::new(transport, rst_pin, busy_pin, delay_source),
+            core: DisplayCore::without_frame_buffer(Protocol::new(profile)),
+        }
     }
+}
 
-    fn spi_write_data(&mut self, data: &[u8]) -> Result<(), Error> {
-        defmt::info!("Sending data chunk: {}", data.len());
-        self.set_data_command(DataCommand::Data)?;
-        self.spi.write(&data).map_err(Error::from_spi_error)?;
-        Ok(())
+#[allow(dead_code)]
+#[cfg(feature = "blocking")]
+impl<
+    T: Transport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> E6Display<T, RST, BUSY, DELAY, S, H>
+{
+    /// Runs the hook on every poll interval while the driver waits for the BUSY line.
+    pub fn with_busy_hook<HOOK: BusyHook>(
+        self,
+        busy_hook: HOOK,
+    ) -> E6Display<T, RST, BUSY, DELAY, S, HOOK> {
+        E6Display {
+            executor: self.executor.with_busy_hook(busy_hook),
+            core: self.core,
+        }
     }
 
-    fn spi_write_frame_buffer(&mut self) -> Result<(), Error> {
-        self.set_data_command(DataCommand::Data)?;
-        let len = crate::nibbles::underlying_data_len(self.frame_buffer.len());
-        let frame_buffer_data = &self.frame_buffer.as_underlying_data().as_ref()[0..len];
-        defmt::info!("Sending data chunk: {}", len);
-        self.spi
-            .write(frame_buffer_data)
-            .map_err(Error::from_spi_error)?;
+    pub fn orientation(&self) -> Orientation {
+        self.core.orientation()
+    }
 
-        Ok(())
+    /// Changes how drawing coordinates map to the panel. The frame buffer keeps its content,
+    /// only later drawing uses the new orientation.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.core.set_orientation(orientation);
     }
 
-    fn spi_write_command_and_read<const R: usize>(
-        &mut self,
-        command: CommandCode,
-    ) -> Result<[u8; R], Error> {
-        let mut result = [0u8; R];
-        self.set_data_command(DataCommand::Command)?;
-        self.spi
-            .transfer(&mut result, &[command as u8])
-            .map_err(Error::from_spi_error)?;
-        Ok(result)
+    /// Replaces the frame buffer with a frame already packed in the controller format, two
+    /// pixels per byte with the first one in the high nibble. The frame is in the native layout
+    /// of the panel, the orientation doesn't apply.
+    pub fn update_packed(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.core.update_packed(data)
     }
 
-    fn spi_write_command_and_data(
+    /// Replaces a window of the frame buffer, given in native panel coordinates, with packed
+    /// rows like [`E6Display::update_packed`]. Every row starts on a byte boundary, so panels
+    /// with an odd width are rejected.
+    pub fn partial_update_packed(
         &mut self,
-        command: CommandCode,
         data: &[u8],
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
     ) -> Result<(), Error> {
-        self.spi_write_command(command)?;
-        self.spi_write_data(data)?;
-        Ok(())
+        self.core.partial_update_packed(data, horizontal, vertical)
     }
 
-    fn reset(&mut self) -> Result<(), Error> {
-        self.rst_pin
-            .set_low()
-            .map_err(Error::from_digital_pin_error)?;
-        self.delay_source.delay_ms(RESET_DELAY_MS);
-        self.rst_pin
-            .set_high()
-            .map_err(Error::from_digital_pin_error)?;
-        self.delay_source.delay_ms(RESET_DELAY_MS);
-        self.busy_wait()?;
-        Ok(())
+    /// Draws `sprite` with its top left corner at the logical `position`, clipped to the panel.
+    /// The frame buffer has to hold the whole panel.
+    pub fn blit<I: AsMut<[u8]> + AsRef<[u8]>, M: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        sprite: &Sprite<'_, I, E6Color, M>,
+        position: Point,
+    ) -> Result<(), Error> {
+        self.core.blit(sprite, position)
     }
 
-    fn busy_wait(&mut self) -> Result<(), Error> {
-        self.busy_wait_timeout(BUSY_WAIT_TIMEOUT_MS)
+    /// Refreshes the whole panel with pixels computed on the fly instead of the frame buffer,
+    /// e.g. on a driver created with [`E6Display::without_frame_buffer`]. `pixel` is called
+    /// once per logical point, in the panel's scan order.
+    pub fn refresh_from_fn(&mut self, pixel: impl FnMut(Point) -> E6Color) -> Result<(), Error> {
+        let mut pixels = self.core.pixels(pixel);
+        self.run_with(Operation::Refresh, &mut pixels)
     }
 
-    fn busy_wait_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
-        defmt::info!("The display could be busy, waiting...");
-        let mut count = (timeout.as_millis() as u32 / BUSY_WAIT_DELAY_MS) + 1;
-        while count > 0
-            && self
-                .busy_pin
-                .is_low()
-                .map_err(Error::from_digital_pin_error)?
-        {
-            self.delay_source.delay_ms(BUSY_WAIT_DELAY_MS);
-            count.sub_assign(1);
-        }
-        defmt::info!("The display is free, continue...");
-        Ok(())
+    /// Refreshes the whole panel row by row from `fill_row`, which gets the panel row index
+    /// and a row of `row_buffer` to fill, in panel coordinates regardless of the orientation.
+    /// `row_buffer` needs half a byte per pixel of a row, and the panel width must be even.
+    pub fn refresh_from_rows(
+        &mut self,
+        row_buffer: &mut [u8],
+        fill_row: impl FnMut(u16, &mut Nibbles<&mut [u8], E6Color>),
+    ) -> Result<(), Error> {
+        let mut rows = self.core.rows(row_buffer, fill_row)?;
+        self.run_with(Operation::Refresh, &mut rows)
     }
 
-    fn refresh_display(&mut self) -> Result<(), Error> {
-        self.power_on()?;
-        self.busy_wait()?;
-        //self.spi_write_command_and_data(CommandCode::BTST2, &[0x6F, 0x1F, 0x17, 0x49])?;
-        self.display_refresh()?;
-        self.busy_wait()?;
-        self.power_off()?;
-        self.busy_wait()?;
-        Ok(())
+    /// Refreshes the whole panel in horizontal bands that fit into `band_buffer`, without
+    /// touching the frame buffer. For every band, the band is filled with `background` and
+    /// `draw` renders the whole logical display into it, keeping only the band's pixels.
+    /// The panel width must be even and the buffer must hold at least one panel row.
+    pub fn refresh_banded(
+        &mut self,
+        band_buffer: &mut [u8],
+        background: E6Color,
+        draw: impl FnMut(&mut Band<'_>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut bands = self.core.bands(band_buffer, background, draw)?;
+        self.run_with(Operation::Refresh, &mut bands)
     }
 
-    fn send_frame_buffer(&mut self) -> Result<(), Error> {
-        self.spi_write_command(CommandCode::DTM1)?;
-        self.spi_write_frame_buffer()?;
-        let result: [u8; 1] = self.spi_write_command_and_read(CommandCode::DSP)?;
-        defmt::info!("Frame buffer sent, result: {}", result);
-        self.busy_wait()?;
-        Ok(())
+    /// Executes the steps of the operation, sending the frame buffer.
+    fn run(&mut self, operation: Operation) -> Result<(), Error> {
+        let mut frame_buffer = self.core.frame_buffer();
+        self.run_with(operation, &mut frame_buffer)
     }
 
-    fn pixel_index(&self, x: usize, y: usize) -> usize {
-        y * self.width as usize + x
+    /// Executes the steps of the operation, sending the windows from `source`.
+    fn run_with(&mut self, operation: Operation, source: &mut impl Source) -> Result<(), Error> {
+        // The protocol of a single controller never selects one.
+        self.executor
+            .run(&mut self.core, operation, source, |_, _| {})
     }
 }
 
 #[cfg(feature = "blocking")]
 impl<
-    DC: OutputPin,
+    T: Transport,
     RST: OutputPin,
     BUSY: InputPin,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> Display<E6Color> for E6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> Display<E6Color> for E6Display<T, RST, BUSY, DELAY, S, H>
 {
     fn width(&self) -> u16 {
-        self.width
+        self.core.logical_size().0
     }
 
     fn height(&self) -> u16 {
-        self.height
+        self.core.logical_size().1
     }
 }
 
 #[cfg(feature = "blocking")]
 impl<
-    DC: OutputPin,
+    T: Transport,
     RST: OutputPin,
     BUSY: InputPin,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> BlockingDisplay<E6Color> for E6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> BlockingDisplay<E6Color> for E6Display<T, RST, BUSY, DELAY, S, H>
 {
     fn initialize(&mut self) -> Result<(), Error> {
-        defmt::info!("Initialize display");
-        self.reset()?;
-        for (command_code, data) in INIT_SEQUENCE {
-            self.spi_write_command_and_data(*command_code, data)?;
-        }
-        Ok(())
+        self.run(Operation::Initialize)
     }
+
     fn update(&mut self, iter: impl IntoIterator<Item = E6Color>) -> Result<(), Error> {
-        let mut iter = iter.into_iter();
-        for index in 0..self.frame_buffer.len() {
-            if let Some(color) = iter.next() {
-                self.frame_buffer.set(index, color);
-            }
-        }
+        self.core.update(iter);
         Ok(())
     }
 
     fn refresh(&mut self) -> Result<(), Error> {
-        self.send_frame_buffer()?;
-        self.refresh_display()
+        self.core.check_frame_buffer()?;
+        self.run(Operation::Refresh)
+    }
+
+    fn sleep(&mut self) -> Result<(), Error> {
+        self.run(Operation::Sleep)
+    }
+
+    fn wake(&mut self) -> Result<(), Error> {
+        defmt::info!("Wake up from deep sleep");
+        self.run(Operation::Initialize)
+    }
+
+    fn power_state(&self) -> PowerState {
+        self.core.power_state()
     }
 }
 
 #[cfg(feature = "blocking")]
 impl<
-    DC: OutputPin,
+    T: Transport,
     RST: OutputPin,
     BUSY: InputPin,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> PartialUpdate<E6Color> for E6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> PartialUpdate<E6Color> for E6Display<T, RST, BUSY, DELAY, S, H>
 {
     fn partial_update(
         &mut self,
@@ -318,29 +641,21 @@ impl<
         horizontal: RangeInclusive<u16>,
         vertical: RangeInclusive<u16>,
     ) -> Result<(), Error> {
-        let mut iter = iter.into_iter();
-        for y in vertical {
-            for x in horizontal.clone() {
-                if let Some(color) = iter.next() {
-                    self.frame_buffer
-                        .set(self.pixel_index(x as usize, y as usize), color);
-                }
-            }
-        }
+        self.core.partial_update(iter, horizontal, vertical);
         Ok(())
     }
-}
 
-const E6_PALETTE: [DisplayRgbColor; 6] = {
-    [
-        (0, 0, 0),
-        (255, 255, 255),
-        (255, 255, 0),
-        (255, 0, 0),
-        (0, 0, 255),
-        (0, 255, 0),
-    ]
-};
+    fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        let Some(window) = self.core.partial_window(horizontal, vertical)? else {
+            return Ok(());
+        };
+        self.run(Operation::PartialRefresh(window))
+    }
+}
 
 #[derive(Format, Copy, Clone, PartialOrd, PartialEq, Debug)]
 #[repr(u8)]
@@ -353,29 +668,62 @@ pub enum E6Color {
     Green = 6,
 }
 
+impl E6Color {
+    /// Color with the given controller index, which is not contiguous: 4 is unused.
+    pub fn try_from_index(index: u8) -> Result<Self, Error> {
+        match index {
+            0 => Ok(E6Color::Black),
+            1 => Ok(E6Color::White),
+            2 => Ok(E6Color::Yellow),
+            3 => Ok(E6Color::Red),
+            5 => Ok(E6Color::Blue),
+            6 => Ok(E6Color::Green),
+            _ => Err(Error::InvalidColor(InvalidColor::Index(index))),
+        }
+    }
+
+    /// Color whose entry in the default palette is exactly the given RGB value, see
+    /// [`Palette::try_color`] for other palettes.
+    pub fn try_from_rgb(color: Rgb888) -> Result<Self, Error> {
+        Palette::DEFAULT.try_color(color)
+    }
+}
+
 impl AsRgbColor for E6Color {
     fn rgb_color(&self) -> DisplayRgbColor {
-        E6_PALETTE[*self as usize]
+        Palette::DEFAULT.rgb(*self)
     }
 }
 
 impl Color for E6Color {}
 
 // Embedded Graphics Impl
+/// Raw values are the controller indices, packed two per byte with the first pixel in the high
+/// nibble like [`Nibbles`], which is the big endian order of `ImageRaw`. `ImageRaw` starts
+/// every row on a byte boundary, so packed images of even width can be drawn as they are.
 impl PixelColor for E6Color {
-    type Raw = ();
+    type Raw = RawU4;
+}
+
+/// Indices that are not E6 colors become white, the color of an empty panel.
+impl From<RawU4> for E6Color {
+    fn from(value: RawU4) -> Self {
+        E6Color::try_from_index(value.into_inner()).unwrap_or(E6Color::White)
+    }
+}
+
+impl From<E6Color> for RawU4 {
+    fn from(value: E6Color) -> Self {
+        RawU4::new(value as u8)
+    }
 }
 
+/// Panics on indices that are not E6 colors, see [`E6Color::try_from_index`].
 impl From<u8> for E6Color {
     fn from(value: u8) -> Self {
-        match value {
-            0 => E6Color::Black,
-            1 => E6Color::White,
-            2 => E6Color::Yellow,
-            3 => E6Color::Red,
-            5 => E6Color::Blue,
-            6 => E6Color::Green,
-            _ => panic!("Unknown E6 color index {}", value),
+        match E6Color::try_from_index(value) {
+            Ok(color) => color,
+            Err(_) => panic!("Unknown E6 color index {}", value),
         }
     }
 }
@@ -388,20 +736,7 @@ impl From<E6Color> for u8 {
 
 impl From<E6Color> for Rgb888 {
     fn from(value: E6Color) -> Self {
-        let triplet = E6_PALETTE[value as usize];
-        Self::new(triplet.0, triplet.1, triplet.2)
-    }
-}
-
-impl From<Rgb888> for E6Color {
-    fn from(value: Rgb888) -> Self {
-        let color: DisplayRgbColor = (value.r(), value.g(), value.b()).into();
-        for (index, c) in E6_PALETTE.iter().enumerate() {
-            if color == *c {
-                return E6Color::from(index as u8);
-            }
-        }
-        panic!("Invalid E6Color: {:?}", color);
+        Palette::DEFAULT.to_rgb888(value)
     }
 }
 
@@ -416,28 +751,28 @@ impl From<BinaryColor> for E6Color {
 
 #[cfg(feature = "blocking")]
 impl<
-    DC: OutputPin,
+    T: Transport,
     RST: OutputPin,
     BUSY: InputPin,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> OriginDimensions for E6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> OriginDimensions for E6Display<T, RST, BUSY, DELAY, S, H>
 {
     fn size(&self) -> Size {
-        Size::new(self.width as u32, self.height as u32)
+        self.core.size()
     }
 }
 
 #[cfg(feature = "blocking")]
 impl<
-    DC: OutputPin,
+    T: Transport,
     RST: OutputPin,
     BUSY: InputPin,
-    SPI: SpiDevice,
     DELAY: DelayNs,
     S: AsMut<[u8]> + AsRef<[u8]>,
-> DrawTarget for E6Display<DC, RST, BUSY, SPI, DELAY, S>
+    H: BusyHook,
+> DrawTarget for E6Display<T, RST, BUSY, DELAY, S, H>
 {
     type Color = E6Color;
     type Error = Error;
@@ -446,10 +781,24 @@ impl<
     where
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
-        for Pixel(p, c) in pixels.into_iter().take(self.frame_buffer.len()) {
-            self.frame_buffer
-                .set(self.pixel_index(p.x as usize, p.y as usize), c);
-        }
+        self.core.draw_iter(pixels);
+        Ok(())
+    }
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.core.fill_contiguous(area, colors);
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.core.fill_solid(area, color);
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.core.clear(color);
         Ok(())
     }
 }
@@ -464,5 +813,5 @@ pub(crate) fn set_data_command(
         } else {
             PinState::Low
         })
-        .map_err(Error::from_digital_pin_error)
+        .map_err(|err| Error::from_digital_pin_error(err).on_pin(PinRole::DataCommand))
 }
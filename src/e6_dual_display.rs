@@ -0,0 +1,445 @@
+//! Driver for panels split between two controllers, like the 13.3" Spectra 6.
+//!
+//! Both controllers share the DC, RST and BUSY lines and sit on separate chip selects.
+//! The primary controller owns the left half of every row and the power circuits, the
+//! secondary one the right half. The frame buffer keeps the whole image, and each
+//! controller only receives its half. The driver talks to them through a [`DualTransport`],
+//! usually a [`FourWire`] over a [`DualSpi`].
+
+use crate::band::Band;
+use crate::busy::{BusyHook, NoBusyHook};
+pub use crate::display::Controller;
+use crate::display::{BlockingDisplay, Display, Error, PartialUpdate, PowerState};
+use crate::display_core::{DisplayCore, Source};
+use crate::e6_display::{BlockingExecutor, E6Color};
+use crate::nibbles::Nibbles;
+use crate::orientation::Orientation;
+use crate::profile::PanelProfile;
+use crate::protocol::{Operation, Protocol};
+use crate::sprite::Sprite;
+use crate::transport::{DualSpi, DualTransport, FourWire};
+use core::ops::RangeInclusive;
+use embedded_graphics::Pixel;
+use embedded_graphics::geometry::{OriginDimensions, Point, Size};
+use embedded_graphics::prelude::DrawTarget;
+use embedded_graphics::primitives::Rectangle;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin};
+use embedded_hal::spi::SpiDevice;
+
+pub struct E6DualDisplay<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook = NoBusyHook,
+> {
+    executor: BlockingExecutor<T, RST, BUSY, DELAY, H>,
+    core: DisplayCore<S>,
+}
+
+impl<
+    DC: OutputPin,
+    RST: OutputPin,
+    BUSY: InputPin,
+    PSPI: SpiDevice,
+    SSPI: SpiDevice,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+> E6DualDisplay<FourWire<DualSpi<PSPI, SSPI>, DC>, RST, BUSY, DELAY, S>
+{
+    /// Creates a driver for the 13.3" 1200x1600 panel.
+    pub fn new(
+        primary_spi: PSPI,
+        secondary_spi: SSPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        Self::with_profile(
+            PanelProfile::SPECTRA6_13IN3,
+            primary_spi,
+            secondary_spi,
+            dc_pin,
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
+    }
+
+    /// The profile describes the whole panel, its width must be a multiple of four so that
+    /// both halves are made of whole bytes.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_profile(
+        profile: PanelProfile,
+        primary_spi: PSPI,
+        secondary_spi: SSPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        Self::with_transport(
+            profile,
+            FourWire::new(DualSpi::new(primary_spi, secondary_spi), dc_pin),
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_with_profile(
+        profile: PanelProfile,
+        primary_spi: PSPI,
+        secondary_spi: SSPI,
+        dc_pin: DC,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        Self::try_with_transport(
+            profile,
+            FourWire::new(DualSpi::new(primary_spi, secondary_spi), dc_pin),
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        )
+    }
+}
+
+impl<T: DualTransport, RST: OutputPin, BUSY: InputPin, DELAY: DelayNs, S: AsMut<[u8]> + AsRef<[u8]>>
+    E6DualDisplay<T, RST, BUSY, DELAY, S>
+{
+    /// Creates a driver that talks to the controllers through the given transport, e.g. a
+    /// [`FourWire`] with a chunk limit.
+    pub fn with_transport(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Self {
+        match Self::try_with_transport(
+            profile,
+            transport,
+            rst_pin,
+            busy_pin,
+            delay_source,
+            frame_buffer,
+        ) {
+            Ok(display) => display,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn try_with_transport(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+        frame_buffer: Nibbles<S, E6Color>,
+    ) -> Result<Self, Error> {
+        let core = DisplayCore::try_new(Protocol::dual(profile), frame_buffer)?;
+        check_resolution(&profile)?;
+        Ok(Self {
+            executor: BlockingExecutor::new(transport, rst_pin, busy_pin, delay_source),
+            core,
+        })
+    }
+}
+
+impl<T: DualTransport, RST: OutputPin, BUSY: InputPin, DELAY: DelayNs>
+    E6DualDisplay<T, RST, BUSY, DELAY, [u8; 0]>
+{
+    /// Creates a driver that keeps no image. Drawing to it does nothing, and the panel is
+    /// refreshed with [`E6DualDisplay::refresh_from_fn`] or
+    /// [`E6DualDisplay::refresh_from_rows`]. Panics on widths that aren't a multiple of four.
+    pub fn without_frame_buffer(
+        profile: PanelProfile,
+        transport: T,
+        rst_pin: RST,
+        busy_pin: BUSY,
+        delay_source: DELAY,
+    ) -> Self {
+        if let Err(error) = check_resolution(&profile) {
+            panic!("{}", error);
+        }
+        Self {
+            executor: BlockingExecutor::new(transport, rst_pin, busy_pin, delay_source),
+            core: DisplayCore::without_frame_buffer(Protocol::dual(profile)),
+        }
+    }
+}
+
+impl<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> E6DualDisplay<T, RST, BUSY, DELAY, S, H>
+{
+    /// Runs the hook on every poll interval while the driver waits for the BUSY line.
+    pub fn with_busy_hook<HOOK: BusyHook>(
+        self,
+        busy_hook: HOOK,
+    ) -> E6DualDisplay<T, RST, BUSY, DELAY, S, HOOK> {
+        E6DualDisplay {
+            executor: self.executor.with_busy_hook(busy_hook),
+            core: self.core,
+        }
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.core.orientation()
+    }
+
+    /// Changes how drawing coordinates map to the panel. The frame buffer keeps its content,
+    /// only later drawing uses the new orientation.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.core.set_orientation(orientation);
+    }
+
+    /// Replaces the frame buffer with a frame already packed in the controller format, two
+    /// pixels per byte with the first one in the high nibble. The frame is in the native layout
+    /// of the whole panel, the orientation doesn't apply.
+    pub fn update_packed(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.core.update_packed(data)
+    }
+
+    /// Replaces a window of the frame buffer, given in native panel coordinates, with packed
+    /// rows like [`E6DualDisplay::update_packed`].
+    pub fn partial_update_packed(
+        &mut self,
+        data: &[u8],
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.core.partial_update_packed(data, horizontal, vertical)
+    }
+
+    /// Draws `sprite` with its top left corner at the logical `position`, clipped to the panel.
+    /// The frame buffer has to hold the whole panel.
+    pub fn blit<I: AsMut<[u8]> + AsRef<[u8]>, M: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        sprite: &Sprite<'_, I, E6Color, M>,
+        position: Point,
+    ) -> Result<(), Error> {
+        self.core.blit(sprite, position)
+    }
+
+    /// Refreshes the whole panel with pixels computed on the fly instead of the frame buffer,
+    /// e.g. on a driver created with [`E6DualDisplay::without_frame_buffer`]. `pixel` is called
+    /// once per logical point, for the half of the primary controller first.
+    pub fn refresh_from_fn(&mut self, pixel: impl FnMut(Point) -> E6Color) -> Result<(), Error> {
+        let mut pixels = self.core.pixels(pixel);
+        self.run_with(Operation::Refresh, &mut pixels)
+    }
+
+    /// Refreshes the whole panel row by row from `fill_row`, which gets the panel row index
+    /// and a row of `row_buffer` to fill, in panel coordinates regardless of the orientation.
+    /// Each controller receives its half of every row in turn, so `fill_row` is called twice
+    /// per row. `row_buffer` needs half a byte per pixel of a row.
+    pub fn refresh_from_rows(
+        &mut self,
+        row_buffer: &mut [u8],
+        fill_row: impl FnMut(u16, &mut Nibbles<&mut [u8], E6Color>),
+    ) -> Result<(), Error> {
+        let mut rows = self.core.rows(row_buffer, fill_row)?;
+        self.run_with(Operation::Refresh, &mut rows)
+    }
+
+    /// Refreshes the whole panel in horizontal bands that fit into `band_buffer`, without
+    /// touching the frame buffer. For every band, the band is filled with `background` and
+    /// `draw` renders the whole logical display into it, keeping only the band's pixels.
+    /// Each controller receives its half of every band in turn, so every band is drawn twice.
+    /// The buffer must hold at least one panel row.
+    pub fn refresh_banded(
+        &mut self,
+        band_buffer: &mut [u8],
+        background: E6Color,
+        draw: impl FnMut(&mut Band<'_>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut bands = self.core.bands(band_buffer, background, draw)?;
+        self.run_with(Operation::Refresh, &mut bands)
+    }
+
+    /// Executes the steps of the operation, sending the frame buffer.
+    fn run(&mut self, operation: Operation) -> Result<(), Error> {
+        let mut frame_buffer = self.core.frame_buffer();
+        self.run_with(operation, &mut frame_buffer)
+    }
+
+    /// Executes the steps of the operation, sending the windows from `source`. Every window
+    /// lies within the half of the selected controller.
+    fn run_with(&mut self, operation: Operation, source: &mut impl Source) -> Result<(), Error> {
+        self.executor.run(
+            &mut self.core,
+            operation,
+            source,
+            |transport, controller| transport.select(controller),
+        )
+    }
+}
+
+/// Both halves of the panel have to be made of whole bytes.
+fn check_resolution(profile: &PanelProfile) -> Result<(), Error> {
+    if !profile.width.is_multiple_of(4) {
+        return Err(Error::UnsupportedResolution {
+            width: profile.width,
+            height: profile.height,
+        });
+    }
+    Ok(())
+}
+
+impl<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> Display<E6Color> for E6DualDisplay<T, RST, BUSY, DELAY, S, H>
+{
+    fn width(&self) -> u16 {
+        self.core.logical_size().0
+    }
+
+    fn height(&self) -> u16 {
+        self.core.logical_size().1
+    }
+}
+
+impl<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> BlockingDisplay<E6Color> for E6DualDisplay<T, RST, BUSY, DELAY, S, H>
+{
+    fn initialize(&mut self) -> Result<(), Error> {
+        self.run(Operation::Initialize)
+    }
+
+    fn update(&mut self, iter: impl IntoIterator<Item = E6Color>) -> Result<(), Error> {
+        self.core.update(iter);
+        Ok(())
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        self.core.check_frame_buffer()?;
+        self.run(Operation::Refresh)
+    }
+
+    fn sleep(&mut self) -> Result<(), Error> {
+        self.run(Operation::Sleep)
+    }
+
+    fn wake(&mut self) -> Result<(), Error> {
+        defmt::info!("Wake up from deep sleep");
+        self.run(Operation::Initialize)
+    }
+
+    fn power_state(&self) -> PowerState {
+        self.core.power_state()
+    }
+}
+
+impl<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> PartialUpdate<E6Color> for E6DualDisplay<T, RST, BUSY, DELAY, S, H>
+{
+    fn partial_update(
+        &mut self,
+        iter: impl IntoIterator<Item = E6Color>,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.core.partial_update(iter, horizontal, vertical);
+        Ok(())
+    }
+
+    fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        let Some(window) = self.core.partial_window(horizontal, vertical)? else {
+            return Ok(());
+        };
+        self.run(Operation::PartialRefresh(window))
+    }
+}
+
+impl<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> OriginDimensions for E6DualDisplay<T, RST, BUSY, DELAY, S, H>
+{
+    fn size(&self) -> Size {
+        self.core.size()
+    }
+}
+
+impl<
+    T: DualTransport,
+    RST: OutputPin,
+    BUSY: InputPin,
+    DELAY: DelayNs,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    H: BusyHook,
+> DrawTarget for E6DualDisplay<T, RST, BUSY, DELAY, S, H>
+{
+    type Color = E6Color;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.core.draw_iter(pixels);
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.core.fill_contiguous(area, colors);
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.core.fill_solid(area, color);
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.core.clear(color);
+        Ok(())
+    }
+}
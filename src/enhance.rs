@@ -0,0 +1,316 @@
+//! Image enhancement in front of quantization and dithering.
+//!
+//! Colors of the panel are much weaker than those of a monitor, so photos look washed out
+//! unless contrast and saturation are boosted first. [`PixelFilter`]s adjust one pixel at a
+//! time and are chained with [`PixelFilter::then`]. [`Enhancer`] runs a filter on the rows of
+//! an image and sharpens them, keeping four rows in a caller-supplied buffer.
+//!
+//! Parameters are floats, but they are turned into tables and fixed-point factors when a filter
+//! is built, so filtering pixels needs no floating point.
+
+use crate::display::Error;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::RgbColor;
+
+/// Adjustment of single pixels.
+pub trait PixelFilter {
+    fn apply(&self, color: Rgb888) -> Rgb888;
+
+    /// Runs `next` on the output of this filter.
+    fn then<F: PixelFilter>(self, next: F) -> Chain<Self, F>
+    where
+        Self: Sized,
+    {
+        Chain(self, next)
+    }
+}
+
+/// Two filters run one after the other, see [`PixelFilter::then`].
+pub struct Chain<A, B>(A, B);
+
+impl<A: PixelFilter, B: PixelFilter> PixelFilter for Chain<A, B> {
+    fn apply(&self, color: Rgb888) -> Rgb888 {
+        self.1.apply(self.0.apply(color))
+    }
+}
+
+/// Filter that keeps the pixels as they are.
+impl PixelFilter for () {
+    fn apply(&self, color: Rgb888) -> Rgb888 {
+        color
+    }
+}
+
+impl<F: PixelFilter> PixelFilter for &F {
+    fn apply(&self, color: Rgb888) -> Rgb888 {
+        (*self).apply(color)
+    }
+}
+
+/// Tone curve of every channel: white point, brightness, contrast and gamma, applied in this
+/// order through a lookup table per channel.
+pub struct Tone {
+    white_point: Rgb888,
+    brightness: f32,
+    contrast: f32,
+    gamma: f32,
+    tables: [[u8; 256]; 3],
+}
+
+impl Tone {
+    /// Curve that keeps the pixels as they are.
+    pub fn new() -> Self {
+        Self {
+            white_point: Rgb888::WHITE,
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            tables: [core::array::from_fn(|value| value as u8); 3],
+        }
+    }
+
+    /// Color that becomes white, e.g. the paper of a scanned image.
+    pub fn with_white_point(self, white_point: Rgb888) -> Self {
+        Self {
+            white_point,
+            ..self
+        }
+        .build()
+    }
+
+    /// Added to every channel, from -1.0 (black) to 1.0 (white).
+    pub fn with_brightness(self, brightness: f32) -> Self {
+        Self {
+            brightness: brightness.clamp(-1.0, 1.0),
+            ..self
+        }
+        .build()
+    }
+
+    /// Factor of the distance from the middle gray, 1.0 keeps the contrast.
+    pub fn with_contrast(self, contrast: f32) -> Self {
+        Self {
+            contrast: contrast.max(0.0),
+            ..self
+        }
+        .build()
+    }
+
+    /// Gamma correction, values above 1.0 brighten the midtones.
+    pub fn with_gamma(self, gamma: f32) -> Self {
+        Self {
+            gamma: gamma.max(0.01),
+            ..self
+        }
+        .build()
+    }
+
+    fn build(mut self) -> Self {
+        let white = [
+            self.white_point.r(),
+            self.white_point.g(),
+            self.white_point.b(),
+        ];
+        for (table, white) in self.tables.iter_mut().zip(white) {
+            for (value, entry) in table.iter_mut().enumerate() {
+                let mut value = (value as f32 / white.max(1) as f32).min(1.0);
+                value += self.brightness;
+                value = ((value - 0.5) * self.contrast + 0.5).clamp(0.0, 1.0);
+                value = pow(value, 1.0 / self.gamma);
+                *entry = (value * 255.0 + 0.5) as u8;
+            }
+        }
+        self
+    }
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PixelFilter for Tone {
+    fn apply(&self, color: Rgb888) -> Rgb888 {
+        Rgb888::new(
+            self.tables[0][color.r() as usize],
+            self.tables[1][color.g() as usize],
+            self.tables[2][color.b() as usize],
+        )
+    }
+}
+
+/// Scales the distance of every channel from the luma of the pixel.
+pub struct Saturation {
+    /// Factor in 1/256.
+    factor: i32,
+}
+
+impl Saturation {
+    /// 0.0 turns the pixels gray, 1.0 keeps them and larger values boost the colors.
+    pub fn new(factor: f32) -> Self {
+        Self {
+            factor: (factor.max(0.0) * 256.0 + 0.5) as i32,
+        }
+    }
+}
+
+impl PixelFilter for Saturation {
+    fn apply(&self, color: Rgb888) -> Rgb888 {
+        let [r, g, b] = [color.r(), color.g(), color.b()].map(|channel| channel as i32);
+        let luma = (77 * r + 150 * g + 29 * b) >> 8;
+        let saturate =
+            |channel: i32| (luma + (((channel - luma) * self.factor) >> 8)).clamp(0, 255) as u8;
+        Rgb888::new(saturate(r), saturate(g), saturate(b))
+    }
+}
+
+/// Runs a [`PixelFilter`] on the rows of an image and sharpens them.
+///
+/// Sharpening needs the rows around a row, so every enhanced row is returned one row later:
+/// [`Enhancer::push_row`] returns the row before the pushed one and [`Enhancer::finish`] the
+/// last row.
+pub struct Enhancer<'a, F> {
+    filter: F,
+    /// Sharpening amount in 1/256.
+    sharpen: i32,
+    width: usize,
+    /// Last three filtered rows, followed by the enhanced one.
+    rows: &'a mut [Rgb888],
+    pushed: usize,
+    finished: bool,
+}
+
+impl<'a, F: PixelFilter> Enhancer<'a, F> {
+    /// Entries of the row buffer needed for images of the given width.
+    pub const fn buffer_len(width: usize) -> usize {
+        4 * width
+    }
+
+    /// `rows` needs [`Enhancer::buffer_len`] entries.
+    pub fn new(filter: F, width: usize, rows: &'a mut [Rgb888]) -> Result<Self, Error> {
+        let required = Self::buffer_len(width);
+        if rows.len() < required {
+            return Err(Error::BufferTooSmall {
+                required,
+                len: rows.len(),
+            });
+        }
+        Ok(Self {
+            filter,
+            sharpen: 0,
+            width,
+            rows: &mut rows[..required],
+            pushed: 0,
+            finished: false,
+        })
+    }
+
+    /// Unsharp masking with the given amount, 0.0 doesn't sharpen.
+    pub fn with_sharpening(self, amount: f32) -> Self {
+        Self {
+            sharpen: (amount.max(0.0) * 256.0 + 0.5) as i32,
+            ..self
+        }
+    }
+
+    /// Filters the next row of the image and returns the enhanced previous row, if any.
+    /// `row` needs at least the width of the image.
+    pub fn push_row(&mut self, row: &[Rgb888]) -> Result<Option<&[Rgb888]>, Error> {
+        if row.len() < self.width {
+            return Err(Error::BufferTooSmall {
+                required: self.width,
+                len: row.len(),
+            });
+        }
+        let slot = self.slot(self.pushed);
+        for (target, color) in self.rows[slot].iter_mut().zip(row) {
+            *target = self.filter.apply(*color);
+        }
+        self.pushed += 1;
+        if self.pushed < 2 {
+            return Ok(None);
+        }
+        Ok(Some(self.enhance(self.pushed - 2)))
+    }
+
+    /// Returns the enhanced last row, once all rows are pushed.
+    pub fn finish(&mut self) -> Option<&[Rgb888]> {
+        if self.pushed == 0 || self.finished {
+            return None;
+        }
+        self.finished = true;
+        Some(self.enhance(self.pushed - 1))
+    }
+
+    /// Buffer range of the filtered row with the given index.
+    fn slot(&self, row: usize) -> core::ops::Range<usize> {
+        let start = row % 3 * self.width;
+        start..start + self.width
+    }
+
+    /// Sharpens the row with the rows around it, repeating the rows at the image borders.
+    fn enhance(&mut self, row: usize) -> &[Rgb888] {
+        let width = self.width;
+        let (filtered, enhanced) = self.rows.split_at_mut(3 * width);
+        let rows =
+            [row.saturating_sub(1), row, (row + 1).min(self.pushed - 1)].map(|row| row % 3 * width);
+        let [above, center, below] = rows;
+        for x in 0..width {
+            let left = x.saturating_sub(1);
+            let right = (x + 1).min(width - 1);
+            let neighbours = [
+                filtered[above + x],
+                filtered[below + x],
+                filtered[center + left],
+                filtered[center + right],
+            ];
+            let pixel = filtered[center + x];
+            let sharpen = |channel: fn(&Rgb888) -> u8| {
+                let value = channel(&pixel) as i32;
+                let laplacian =
+                    4 * value - neighbours.iter().map(|n| channel(n) as i32).sum::<i32>();
+                (value + ((laplacian * self.sharpen) >> 10)).clamp(0, 255) as u8
+            };
+            enhanced[x] = Rgb888::new(sharpen(Rgb888::r), sharpen(Rgb888::g), sharpen(Rgb888::b));
+        }
+        enhanced
+    }
+}
+
+/// `x` to the power of `y` for `x` in `0.0..=1.0`, as `core` has no `powf`.
+fn pow(x: f32, y: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    exp2(y * log2(x))
+}
+
+fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    let mantissa = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+    // ln(m) = 2 atanh((m - 1) / (m + 1)), with the series converging fast for m in 1..2.
+    let t = (mantissa - 1.0) / (mantissa + 1.0);
+    let t2 = t * t;
+    let mut term = t;
+    let mut series = 0.0;
+    for n in (1..12).step_by(2) {
+        series += term / n as f32;
+        term *= t2;
+    }
+    exponent as f32 + 2.0 * series * core::f32::consts::LOG2_E
+}
+
+fn exp2(x: f32) -> f32 {
+    let x = x.clamp(-126.0, 127.0);
+    let whole = x as i32 - (x < 0.0 && x != (x as i32) as f32) as i32;
+    let fraction = (x - whole as f32) * core::f32::consts::LN_2;
+    let mut term = 1.0;
+    let mut series = 1.0;
+    for n in 1..10 {
+        term *= fraction / n as f32;
+        series += term;
+    }
+    series * f32::from_bits(((whole + 127) as u32) << 23)
+}
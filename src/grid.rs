@@ -0,0 +1,556 @@
+//! Two-dimensional access to a [`PackedBuffer`].
+//!
+//! [`Grid`] lays rows of `width` elements out every `stride` elements of the buffer, so callers
+//! don't compute `y * width + x` themselves. With [`Grid::padded_stride`] every row starts on a
+//! byte boundary, which odd widths of 4-bit pixels need to hand out rows as packed bytes.
+//! [`GridView`] and [`GridViewMut`] are sub-rectangles of a grid with their own coordinates.
+
+use crate::display::Error;
+use crate::packed::{BitOrder, MsbFirst, PackedBuffer, packed_len};
+use crate::sprite::Sprite;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::primitives::Rectangle;
+
+/// Rectangle of a grid, in grid coordinates.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Area {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Area {
+    /// Part of `rectangle`, relative to this area, that lies within it.
+    fn clip(&self, rectangle: &Rectangle) -> Area {
+        let bounds = Rectangle::new(
+            Point::zero(),
+            Size::new(self.width as u32, self.height as u32),
+        );
+        let clipped = rectangle.intersection(&bounds);
+        if clipped.is_zero_sized() {
+            return Area {
+                width: 0,
+                height: 0,
+                ..*self
+            };
+        }
+        Area {
+            x: self.x + clipped.top_left.x as usize,
+            y: self.y + clipped.top_left.y as usize,
+            width: clipped.size.width as usize,
+            height: clipped.size.height as usize,
+        }
+    }
+
+    fn row(&self, y: usize) -> Area {
+        Area {
+            y: self.y + y,
+            height: 1,
+            ..*self
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+pub struct Grid<
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize = 4,
+    O: BitOrder = MsbFirst,
+> {
+    buffer: PackedBuffer<S, E, BITS, O>,
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    Grid<S, E, BITS, O>
+{
+    /// Grid whose rows follow each other without padding.
+    pub fn new(data: S, width: usize, height: usize) -> Self {
+        Self::with_stride(data, width, height, width)
+    }
+
+    pub fn try_new(data: S, width: usize, height: usize) -> Result<Self, Error> {
+        Self::try_with_stride(data, width, height, width)
+    }
+
+    /// Grid whose rows start every `stride` elements. Panics when `stride` is less than the
+    /// width or `data` is too small.
+    pub fn with_stride(data: S, width: usize, height: usize, stride: usize) -> Self {
+        match Self::try_with_stride(data, width, height, stride) {
+            Ok(grid) => grid,
+            Err(error) => panic!("Invalid grid: {:?}", error),
+        }
+    }
+
+    pub fn try_with_stride(
+        data: S,
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) -> Result<Self, Error> {
+        if stride < width {
+            return Err(Error::InvalidStride { stride, width });
+        }
+        Ok(Self {
+            buffer: PackedBuffer::try_new(data, stride * height)?,
+            width,
+            height,
+            stride,
+        })
+    }
+
+    /// Smallest stride that starts every row on a byte boundary.
+    pub const fn padded_stride(width: usize) -> usize {
+        packed_len(width, BITS) * 8 / BITS
+    }
+
+    /// Bytes needed for a grid of the given size and stride.
+    pub const fn data_len(height: usize, stride: usize) -> usize {
+        packed_len(stride * height, BITS)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    fn area(&self) -> Area {
+        Area {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Buffer index of a point of the area, or `None` when it is outside of the area.
+    fn index(&self, area: Area, x: usize, y: usize) -> Option<usize> {
+        (x < area.width && y < area.height).then(|| (area.y + y) * self.stride + area.x + x)
+    }
+
+    fn try_index(&self, area: Area, x: usize, y: usize) -> Result<usize, Error> {
+        self.index(area, x, y).ok_or(Error::OutOfBounds {
+            index: y.saturating_mul(area.width).saturating_add(x),
+            len: area.len(),
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> E {
+        self.as_view().get(x, y)
+    }
+
+    pub fn try_get(&self, x: usize, y: usize) -> Result<E, Error> {
+        self.as_view().try_get(x, y)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: E) {
+        self.as_view_mut().set(x, y, value)
+    }
+
+    pub fn try_set(&mut self, x: usize, y: usize, value: E) -> Result<(), Error> {
+        self.as_view_mut().try_set(x, y, value)
+    }
+
+    /// Packed bytes of a row, when every row starts on a byte boundary.
+    pub fn row_data(&self, y: usize) -> Option<&[u8]> {
+        let bytes = self.row_bytes(y)?;
+        Some(&self.buffer.as_underlying_data().as_ref()[bytes])
+    }
+
+    pub fn row_data_mut(&mut self, y: usize) -> Option<&mut [u8]> {
+        let bytes = self.row_bytes(y)?;
+        Some(&mut self.buffer.as_underlying_data_mut().as_mut()[bytes])
+    }
+
+    fn row_bytes(&self, y: usize) -> Option<core::ops::Range<usize>> {
+        if y >= self.height || !(self.stride * BITS).is_multiple_of(8) {
+            return None;
+        }
+        let start = y * self.stride * BITS / 8;
+        Some(start..start + packed_len(self.width, BITS))
+    }
+
+    pub fn as_view(&self) -> GridView<'_, S, E, BITS, O> {
+        GridView {
+            grid: self,
+            area: self.area(),
+        }
+    }
+
+    pub fn as_view_mut(&mut self) -> GridViewMut<'_, S, E, BITS, O> {
+        let area = self.area();
+        GridViewMut { grid: self, area }
+    }
+
+    /// Part of `area` that lies within the grid.
+    pub fn view(&self, area: &Rectangle) -> GridView<'_, S, E, BITS, O> {
+        GridView {
+            grid: self,
+            area: self.area().clip(area),
+        }
+    }
+
+    pub fn view_mut(&mut self, area: &Rectangle) -> GridViewMut<'_, S, E, BITS, O> {
+        let area = self.area().clip(area);
+        GridViewMut { grid: self, area }
+    }
+
+    pub fn rows(&self) -> Rows<'_, S, E, BITS, O> {
+        self.as_view().rows()
+    }
+
+    pub fn iter(&self) -> GridIter<'_, S, E, BITS, O> {
+        self.as_view().iter()
+    }
+
+    pub fn fill(&mut self, value: E) {
+        self.as_view_mut().fill(value)
+    }
+
+    /// Replaces every element with the result of `f`, called with its coordinates in row-major
+    /// order.
+    pub fn update(&mut self, f: impl FnMut((usize, usize), E) -> E) {
+        self.as_view_mut().update(f)
+    }
+
+    /// Draws `sprite` with its top left corner at `position`, clipped to the grid.
+    pub fn blit<T: AsMut<[u8]> + AsRef<[u8]>, M: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        sprite: &Sprite<'_, T, E, M, BITS, O>,
+        position: Point,
+    ) {
+        self.as_view_mut().blit(sprite, position)
+    }
+
+    pub fn buffer(&self) -> &PackedBuffer<S, E, BITS, O> {
+        &self.buffer
+    }
+
+    pub fn into_buffer(self) -> PackedBuffer<S, E, BITS, O> {
+        self.buffer
+    }
+}
+
+/// Rectangle of a [`Grid`] with coordinates relative to its top left corner.
+pub struct GridView<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize = 4,
+    O: BitOrder = MsbFirst,
+> {
+    grid: &'a Grid<S, E, BITS, O>,
+    area: Area,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder> Clone
+    for GridView<'_, S, E, BITS, O>
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder> Copy
+    for GridView<'_, S, E, BITS, O>
+{
+}
+
+impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    GridView<'a, S, E, BITS, O>
+{
+    pub fn width(&self) -> usize {
+        self.area.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.area.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> E {
+        match self.grid.index(self.area, x, y) {
+            Some(index) => self.grid.buffer.get(index),
+            None => panic!("Index out of bounds"),
+        }
+    }
+
+    pub fn try_get(&self, x: usize, y: usize) -> Result<E, Error> {
+        let index = self.grid.try_index(self.area, x, y)?;
+        Ok(self.grid.buffer.get(index))
+    }
+
+    /// Buffer index of a point of the view, or `None` when it is outside of the view.
+    pub(crate) fn index(&self, x: usize, y: usize) -> Option<usize> {
+        self.grid.index(self.area, x, y)
+    }
+
+    pub(crate) fn buffer(&self) -> &'a PackedBuffer<S, E, BITS, O> {
+        &self.grid.buffer
+    }
+
+    /// Part of `area`, relative to this view, that lies within it.
+    pub fn view(&self, area: &Rectangle) -> GridView<'a, S, E, BITS, O> {
+        GridView {
+            grid: self.grid,
+            area: self.area.clip(area),
+        }
+    }
+
+    pub fn row(&self, y: usize) -> Option<GridView<'a, S, E, BITS, O>> {
+        (y < self.area.height).then(|| GridView {
+            grid: self.grid,
+            area: self.area.row(y),
+        })
+    }
+
+    pub fn rows(&self) -> Rows<'a, S, E, BITS, O> {
+        Rows {
+            view: *self,
+            front: 0,
+            back: self.area.height,
+        }
+    }
+
+    /// Coordinates and elements in row-major order.
+    pub fn iter(&self) -> GridIter<'a, S, E, BITS, O> {
+        GridIter {
+            view: *self,
+            front: 0,
+            back: self.area.len(),
+        }
+    }
+}
+
+impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    IntoIterator for GridView<'a, S, E, BITS, O>
+{
+    type Item = ((usize, usize), E);
+    type IntoIter = GridIter<'a, S, E, BITS, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Mutable rectangle of a [`Grid`] with coordinates relative to its top left corner.
+pub struct GridViewMut<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize = 4,
+    O: BitOrder = MsbFirst,
+> {
+    grid: &'a mut Grid<S, E, BITS, O>,
+    area: Area,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    GridViewMut<'_, S, E, BITS, O>
+{
+    pub fn width(&self) -> usize {
+        self.area.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.area.height
+    }
+
+    pub fn as_view(&self) -> GridView<'_, S, E, BITS, O> {
+        GridView {
+            grid: self.grid,
+            area: self.area,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> E {
+        self.as_view().get(x, y)
+    }
+
+    pub fn try_get(&self, x: usize, y: usize) -> Result<E, Error> {
+        self.as_view().try_get(x, y)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: E) {
+        match self.grid.index(self.area, x, y) {
+            Some(index) => self.grid.buffer.set(index, value),
+            None => panic!("Index out of bounds"),
+        }
+    }
+
+    pub fn try_set(&mut self, x: usize, y: usize, value: E) -> Result<(), Error> {
+        let index = self.grid.try_index(self.area, x, y)?;
+        self.grid.buffer.set(index, value);
+        Ok(())
+    }
+
+    /// Part of `area`, relative to this view, that lies within it.
+    pub fn view_mut(&mut self, area: &Rectangle) -> GridViewMut<'_, S, E, BITS, O> {
+        GridViewMut {
+            grid: self.grid,
+            area: self.area.clip(area),
+        }
+    }
+
+    /// Sets every element of the view, a row of whole bytes at a time.
+    pub fn fill(&mut self, value: E) {
+        let bits = value.into();
+        for y in 0..self.area.height {
+            let start = (self.area.y + y) * self.grid.stride + self.area.x;
+            self.grid
+                .buffer
+                .fill(start..start + self.area.width, E::from(bits));
+        }
+    }
+
+    /// Draws `sprite` with its top left corner at `position`, clipped to the view.
+    pub fn blit<T: AsMut<[u8]> + AsRef<[u8]>, M: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        sprite: &Sprite<'_, T, E, M, BITS, O>,
+        position: Point,
+    ) {
+        let size = Size::new(sprite.width() as u32, sprite.height() as u32);
+        let target = self.area.clip(&Rectangle::new(position, size));
+        let x = ((target.x - self.area.x) as i32 - position.x) as usize;
+        let y = ((target.y - self.area.y) as i32 - position.y) as usize;
+        for row in 0..target.height {
+            let start = (target.y + row) * self.grid.stride + target.x;
+            sprite.blit_row(&mut self.grid.buffer, start, (x, y + row), target.width);
+        }
+    }
+
+    /// Replaces every element with the result of `f`, called with its coordinates in row-major
+    /// order.
+    pub fn update(&mut self, mut f: impl FnMut((usize, usize), E) -> E) {
+        for y in 0..self.area.height {
+            for x in 0..self.area.width {
+                let index = (self.area.y + y) * self.grid.stride + self.area.x + x;
+                let value = f((x, y), self.grid.buffer.get(index));
+                self.grid.buffer.set(index, value);
+            }
+        }
+    }
+}
+
+/// Rows of a view as views of their own, see [`GridView::rows`].
+pub struct Rows<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize = 4,
+    O: BitOrder = MsbFirst,
+> {
+    view: GridView<'a, S, E, BITS, O>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    Iterator for Rows<'a, S, E, BITS, O>
+{
+    type Item = GridView<'a, S, E, BITS, O>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.view.row(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    DoubleEndedIterator for Rows<'_, S, E, BITS, O>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.view.row(self.back)
+    }
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    ExactSizeIterator for Rows<'_, S, E, BITS, O>
+{
+}
+
+/// Coordinates and elements of a view in row-major order, see [`GridView::iter`].
+pub struct GridIter<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize = 4,
+    O: BitOrder = MsbFirst,
+> {
+    view: GridView<'a, S, E, BITS, O>,
+    front: usize,
+    back: usize,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    GridIter<'_, S, E, BITS, O>
+{
+    fn element(&self, position: usize) -> ((usize, usize), E) {
+        let (x, y) = (
+            position % self.view.area.width,
+            position / self.view.area.width,
+        );
+        ((x, y), self.view.get(x, y))
+    }
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder> Iterator
+    for GridIter<'_, S, E, BITS, O>
+{
+    type Item = ((usize, usize), E);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        Some(self.element(self.front - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    DoubleEndedIterator for GridIter<'_, S, E, BITS, O>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(self.element(self.back))
+    }
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    ExactSizeIterator for GridIter<'_, S, E, BITS, O>
+{
+}
@@ -3,17 +3,49 @@
 extern crate alloc;
 #[cfg(feature = "async")]
 pub mod async_e6_display;
+pub mod band;
+pub mod busy;
 pub mod display;
+mod display_core;
+pub mod dither;
+pub mod enhance;
 
 pub mod e6_display;
+#[cfg(feature = "blocking")]
+pub mod e6_dual_display;
+pub mod grid;
 mod nibbles;
+pub mod orientation;
+pub mod packed;
+pub mod palette;
+pub mod profile;
+mod protocol;
+pub mod quantize;
+pub mod sprite;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
+pub mod typestate;
 
 pub mod prelude {
+    pub use crate::band::Band;
+    pub use crate::busy::{BusyHook, BusyPhase, BusyPolicy, NoBusyHook};
     pub use crate::display::Display;
+    pub use crate::display::PowerState;
+    pub use crate::dither::{DitherMethod, Ditherer};
     pub use crate::e6_display::E6Color;
+    pub use crate::enhance::{Enhancer, PixelFilter, Saturation, Tone};
+    pub use crate::grid::{Grid, GridView, GridViewMut};
     pub use crate::nibbles::Nibbles;
     pub use crate::nibbles::NibblesIterator;
     pub use crate::nibbles::underlying_data_len;
+    pub use crate::orientation::{Orientation, Rotation};
+    pub use crate::packed::{LsbFirst, MsbFirst, PackedBuffer};
+    pub use crate::palette::Palette;
+    pub use crate::profile::PanelProfile;
+    pub use crate::quantize::{Distance, QuantizeTable, nearest, quantize, quantize_with};
+    pub use crate::sprite::Sprite;
+    pub use crate::transport::{DualSpi, DualTransport, FourWire, ThreeWire, Transport};
 
     #[cfg(feature = "blocking")]
     pub use crate::e6_display::BlockingDisplay;
@@ -21,6 +53,10 @@ pub mod prelude {
     pub use crate::e6_display::E6Display;
     #[cfg(feature = "blocking")]
     pub use crate::e6_display::PartialUpdate;
+    #[cfg(feature = "blocking")]
+    pub use crate::e6_dual_display::E6DualDisplay;
+    #[cfg(feature = "blocking")]
+    pub use crate::typestate::Panel;
 
     #[cfg(feature = "async")]
     pub use crate::async_e6_display::AsyncE6Display;
@@ -28,4 +64,8 @@ pub mod prelude {
     pub use crate::display::AsyncDisplay;
     #[cfg(feature = "async")]
     pub use crate::display::AsyncPartialUpdate;
+    #[cfg(feature = "async")]
+    pub use crate::transport::AsyncTransport;
+    #[cfg(feature = "async")]
+    pub use crate::typestate::AsyncPanel;
 }
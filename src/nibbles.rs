@@ -1,93 +1,70 @@
-use core::marker::PhantomData;
-use core::ops::AddAssign;
+//! The 4-bit frame buffer of the Spectra 6 drivers.
+
+use crate::packed::{MsbFirst, PackedBuffer, PackedIterator, packed_len};
 
 pub type Nibble = u8;
 
-pub struct Nibbles<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<Nibble> + From<Nibble>> {
-    data: S,
-    _phantom: PhantomData<E>,
-    len: usize,
-}
+/// Pixels packed two per byte, the first one in the high nibble like the controller expects.
+pub type Nibbles<S, E> = PackedBuffer<S, E, 4, MsbFirst>;
 
-impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<Nibble> + From<Nibble>> Nibbles<S, E> {
-    pub fn new(mut data: S, len: usize) -> Self {
-        assert!(
-            data.as_mut().len() * 2 >= len,
-            "Nibbles underlying slice doesn't have enough space"
-        );
-        Self {
-            data,
-            _phantom: Default::default(),
-            len,
-        }
-    }
+pub type NibblesIterator<'a, S, E> = PackedIterator<'a, S, E, 4, MsbFirst>;
 
-    pub fn len(&self) -> usize {
-        self.len
-    }
+pub const fn underlying_data_len(nibbles_len: usize) -> usize {
+    packed_len(nibbles_len, 4)
+}
 
-    pub fn get(&self, index: usize) -> E {
-        if index >= self.len {
-            panic!("Index out of bounds");
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<Nibble> + From<Nibble>> Nibbles<S, E> {
+    /// Sets up to `count` nibbles from `start` on to the next values, writing pairs of them as
+    /// whole bytes. Stops early when `values` runs out.
+    pub(crate) fn set_from(
+        &mut self,
+        start: usize,
+        count: usize,
+        values: &mut impl Iterator<Item = E>,
+    ) {
+        let end = (start + count).min(self.len());
+        let mut index = start;
+        if index < end && !index.is_multiple_of(2) {
+            let Some(value) = values.next() else {
+                return;
+            };
+            self.set(index, value);
+            index += 1;
         }
-        let left = index % 2 == 0;
-        let pair = self.data.as_ref()[index / 2];
-        (if left { pair >> 4 } else { pair & 0x0F }).into()
-    }
-
-    pub fn set(&mut self, index: usize, value: E) {
-        if index >= self.len {
-            panic!("Index out of bounds");
+        while index + 1 < end {
+            let Some(high) = values.next() else {
+                return;
+            };
+            let Some(low) = values.next() else {
+                self.set(index, high);
+                return;
+            };
+            self.as_underlying_data_mut().as_mut()[index / 2] =
+                (high.into() << 4) | (low.into() & 0x0F);
+            index += 2;
         }
-        let left = index % 2 == 0;
-        let pair = &mut self.data.as_mut()[index / 2];
-        *pair = if left {
-            (*pair & 0x0F) | (value.into() << 4)
-        } else {
-            (*pair & 0xF0) | (value.into() & 0x0F)
+        if index < end
+            && let Some(value) = values.next()
+        {
+            self.set(index, value);
         }
     }
 
-    pub fn as_underlying_data(&self) -> &S {
-        &self.data
-    }
-}
-
-pub struct NibblesIterator<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<Nibble> + From<Nibble>> {
-    nibbles: &'a Nibbles<S, E>,
-    index: usize,
-}
-
-pub const fn underlying_data_len(nibbles_len: usize) -> usize {
-    (nibbles_len + 1) / 2
-}
-
-impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<Nibble> + From<Nibble>> Iterator
-    for NibblesIterator<'a, S, E>
-{
-    type Item = E;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.nibbles.len() {
-            let result = Some(self.nibbles.get(self.index));
-            self.index.add_assign(1);
-            result
+    /// Copies `count` nibbles packed like this buffer from `packed` to `start` on, as whole
+    /// bytes when `start` is on a byte boundary. The nibbles aren't checked.
+    pub(crate) fn copy_packed(&mut self, start: usize, count: usize, packed: &[u8]) {
+        if start.is_multiple_of(2) {
+            let bytes = count / 2;
+            self.as_underlying_data_mut().as_mut()[start / 2..start / 2 + bytes]
+                .copy_from_slice(&packed[..bytes]);
+            if !count.is_multiple_of(2) {
+                self.set_bits(start + count - 1, packed[bytes] >> 4);
+            }
         } else {
-            None
-        }
-    }
-}
-
-impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<Nibble> + From<Nibble>> IntoIterator
-    for &'a Nibbles<S, E>
-{
-    type Item = E;
-    type IntoIter = NibblesIterator<'a, S, E>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        NibblesIterator {
-            nibbles: self,
-            index: 0,
+            for index in 0..count {
+                let shift = if index.is_multiple_of(2) { 4 } else { 0 };
+                self.set_bits(start + index, (packed[index / 2] >> shift) & 0x0F);
+            }
         }
     }
 }
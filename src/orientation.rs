@@ -0,0 +1,136 @@
+use core::ops::RangeInclusive;
+use defmt::Format;
+use embedded_graphics::geometry::Point;
+
+/// Clockwise rotation of the drawing relative to the panel's native scan direction.
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// How the logical drawing coordinates map to the panel.
+///
+/// Mirroring is applied to the logical image first, the rotation afterwards. With
+/// [`Rotation::Deg90`] and [`Rotation::Deg270`] the logical width and height are swapped.
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    /// Flips the logical image left to right.
+    pub mirror_horizontal: bool,
+    /// Flips the logical image top to bottom.
+    pub mirror_vertical: bool,
+}
+
+impl Orientation {
+    pub const fn new(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            mirror_horizontal: false,
+            mirror_vertical: false,
+        }
+    }
+
+    pub const fn with_mirror_horizontal(self, mirror_horizontal: bool) -> Self {
+        Self {
+            mirror_horizontal,
+            ..self
+        }
+    }
+
+    pub const fn with_mirror_vertical(self, mirror_vertical: bool) -> Self {
+        Self {
+            mirror_vertical,
+            ..self
+        }
+    }
+
+    pub const fn swaps_axes(&self) -> bool {
+        matches!(self.rotation, Rotation::Deg90 | Rotation::Deg270)
+    }
+
+    /// Logical size of a panel with the given native size.
+    pub const fn logical_size(&self, width: u16, height: u16) -> (u16, u16) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Maps a logical point to native panel coordinates, or `None` when it is off the panel.
+    pub fn physical_point(&self, point: Point, width: u16, height: u16) -> Option<(u16, u16)> {
+        let (logical_width, logical_height) = self.logical_size(width, height);
+        let x = u16::try_from(point.x).ok().filter(|x| *x < logical_width)?;
+        let y = u16::try_from(point.y)
+            .ok()
+            .filter(|y| *y < logical_height)?;
+        let x = if self.mirror_horizontal {
+            logical_width - 1 - x
+        } else {
+            x
+        };
+        let y = if self.mirror_vertical {
+            logical_height - 1 - y
+        } else {
+            y
+        };
+        Some(match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (width - 1 - y, x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (y, height - 1 - x),
+        })
+    }
+
+    /// Logical point shown at the given native panel coordinates, the inverse of
+    /// [`Orientation::physical_point`].
+    pub fn logical_point(&self, x: u16, y: u16, width: u16, height: u16) -> Point {
+        let (logical_width, logical_height) = self.logical_size(width, height);
+        let (x, y) = match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (y, width - 1 - x),
+            Rotation::Deg180 => (width - 1 - x, height - 1 - y),
+            Rotation::Deg270 => (height - 1 - y, x),
+        };
+        let x = if self.mirror_horizontal {
+            logical_width - 1 - x
+        } else {
+            x
+        };
+        let y = if self.mirror_vertical {
+            logical_height - 1 - y
+        } else {
+            y
+        };
+        Point::new(x as i32, y as i32)
+    }
+
+    /// Native panel ranges covering the logical ranges, clamped to the panel.
+    /// Returns `None` when nothing of the area is on the panel.
+    pub(crate) fn physical_area(
+        &self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+        width: u16,
+        height: u16,
+    ) -> Option<(RangeInclusive<u16>, RangeInclusive<u16>)> {
+        let (logical_width, logical_height) = self.logical_size(width, height);
+        let x_end = (*horizontal.end()).min(logical_width.checked_sub(1)?);
+        let y_end = (*vertical.end()).min(logical_height.checked_sub(1)?);
+        let start = Point::new(*horizontal.start() as i32, *vertical.start() as i32);
+        let end = Point::new(x_end as i32, y_end as i32);
+        if start.x > end.x || start.y > end.y {
+            return None;
+        }
+        let (start_x, start_y) = self.physical_point(start, width, height)?;
+        let (end_x, end_y) = self.physical_point(end, width, height)?;
+        Some((
+            start_x.min(end_x)..=start_x.max(end_x),
+            start_y.min(end_y)..=start_y.max(end_y),
+        ))
+    }
+}
@@ -0,0 +1,250 @@
+//! Buffers of elements packed with 1, 2, 4 or 8 bits each.
+//!
+//! [`PackedBuffer`] stores the elements in caller-supplied bytes, the first element of a byte
+//! in the most significant bits by default. [`Nibbles`](crate::nibbles::Nibbles), the frame
+//! buffer of the Spectra 6 drivers, is the 4-bit buffer in this order. Black/white/red panels,
+//! masks and alpha planes use the other depths.
+
+use crate::display::Error;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+/// Order of the elements within a byte.
+pub trait BitOrder {
+    const MSB_FIRST: bool;
+}
+
+/// The first element of a byte is in its most significant bits, like the controllers expect.
+pub struct MsbFirst;
+
+impl BitOrder for MsbFirst {
+    const MSB_FIRST: bool = true;
+}
+
+/// The first element of a byte is in its least significant bits.
+pub struct LsbFirst;
+
+impl BitOrder for LsbFirst {
+    const MSB_FIRST: bool = false;
+}
+
+/// Bytes needed to pack `len` elements of `bits` bits.
+pub const fn packed_len(len: usize, bits: usize) -> usize {
+    (len * bits).div_ceil(8)
+}
+
+pub struct PackedBuffer<
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize,
+    O: BitOrder = MsbFirst,
+> {
+    data: S,
+    _phantom: PhantomData<(E, O)>,
+    len: usize,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    PackedBuffer<S, E, BITS, O>
+{
+    const ELEMENTS_PER_BYTE: usize = {
+        assert!(
+            BITS == 1 || BITS == 2 || BITS == 4 || BITS == 8,
+            "Packed elements need 1, 2, 4 or 8 bits"
+        );
+        8 / BITS
+    };
+    const MASK: u8 = (0xFF_u16 >> (8 - BITS)) as u8;
+
+    pub fn new(mut data: S, len: usize) -> Self {
+        assert!(
+            data.as_mut().len() * Self::ELEMENTS_PER_BYTE >= len,
+            "Packed buffer underlying slice doesn't have enough space"
+        );
+        Self {
+            data,
+            _phantom: Default::default(),
+            len,
+        }
+    }
+
+    pub fn try_new(data: S, len: usize) -> Result<Self, Error> {
+        let capacity = data.as_ref().len() * Self::ELEMENTS_PER_BYTE;
+        if capacity < len {
+            return Err(Error::BufferTooSmall {
+                required: len,
+                len: capacity,
+            });
+        }
+        Ok(Self::new(data, len))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Position of the element's lowest bit within its byte.
+    fn shift(index: usize) -> usize {
+        let slot = index % Self::ELEMENTS_PER_BYTE;
+        if O::MSB_FIRST {
+            8 - BITS * (slot + 1)
+        } else {
+            BITS * slot
+        }
+    }
+
+    pub fn get(&self, index: usize) -> E {
+        self.get_bits(index).into()
+    }
+
+    pub(crate) fn get_bits(&self, index: usize) -> u8 {
+        if index >= self.len {
+            panic!("Index out of bounds");
+        }
+        let byte = self.data.as_ref()[index / Self::ELEMENTS_PER_BYTE];
+        (byte >> Self::shift(index)) & Self::MASK
+    }
+
+    pub fn set(&mut self, index: usize, value: E) {
+        self.set_bits(index, value.into());
+    }
+
+    pub(crate) fn set_bits(&mut self, index: usize, bits: u8) {
+        if index >= self.len {
+            panic!("Index out of bounds");
+        }
+        let shift = Self::shift(index);
+        let byte = &mut self.data.as_mut()[index / Self::ELEMENTS_PER_BYTE];
+        *byte = (*byte & !(Self::MASK << shift)) | ((bits & Self::MASK) << shift);
+    }
+
+    pub fn try_get(&self, index: usize) -> Result<E, Error> {
+        self.check_index(index)?;
+        Ok(self.get(index))
+    }
+
+    pub fn try_set(&mut self, index: usize, value: E) -> Result<(), Error> {
+        self.check_index(index)?;
+        self.set(index, value);
+        Ok(())
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), Error> {
+        if index < self.len {
+            Ok(())
+        } else {
+            Err(Error::OutOfBounds {
+                index,
+                len: self.len,
+            })
+        }
+    }
+
+    /// Sets the elements in `range`, writing whole bytes between the partial edges.
+    pub fn fill(&mut self, range: Range<usize>, value: E) {
+        let bits = value.into() & Self::MASK;
+        let mut start = range.start;
+        let mut end = range.end.min(self.len);
+        while start < end && !start.is_multiple_of(Self::ELEMENTS_PER_BYTE) {
+            self.set_bits(start, bits);
+            start += 1;
+        }
+        while start < end && !end.is_multiple_of(Self::ELEMENTS_PER_BYTE) {
+            end -= 1;
+            self.set_bits(end, bits);
+        }
+        if start < end {
+            let byte =
+                (0..Self::ELEMENTS_PER_BYTE).fold(0, |byte, slot| byte | (bits << (slot * BITS)));
+            self.data.as_mut()[start / Self::ELEMENTS_PER_BYTE..end / Self::ELEMENTS_PER_BYTE]
+                .fill(byte);
+        }
+    }
+
+    /// Copies `count` elements of `source` from `source_start` on to `start` on, as whole bytes
+    /// where both are at the same position within their bytes.
+    pub(crate) fn copy_from<T: AsMut<[u8]> + AsRef<[u8]>>(
+        &mut self,
+        start: usize,
+        source: &PackedBuffer<T, E, BITS, O>,
+        source_start: usize,
+        count: usize,
+    ) {
+        let per_byte = Self::ELEMENTS_PER_BYTE;
+        let mut offset = 0;
+        if start % per_byte == source_start % per_byte {
+            while offset < count && !(start + offset).is_multiple_of(per_byte) {
+                self.set_bits(start + offset, source.get_bits(source_start + offset));
+                offset += 1;
+            }
+            let bytes = (count - offset) / per_byte;
+            let (target, from) = (
+                (start + offset) / per_byte,
+                (source_start + offset) / per_byte,
+            );
+            self.data.as_mut()[target..target + bytes]
+                .copy_from_slice(&source.data.as_ref()[from..from + bytes]);
+            offset += bytes * per_byte;
+        }
+        for offset in offset..count {
+            self.set_bits(start + offset, source.get_bits(source_start + offset));
+        }
+    }
+
+    pub fn iter(&self) -> PackedIterator<'_, S, E, BITS, O> {
+        self.into_iter()
+    }
+
+    pub fn as_underlying_data(&self) -> &S {
+        &self.data
+    }
+
+    pub fn as_underlying_data_mut(&mut self) -> &mut S {
+        &mut self.data
+    }
+}
+
+pub struct PackedIterator<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    const BITS: usize,
+    O: BitOrder = MsbFirst,
+> {
+    buffer: &'a PackedBuffer<S, E, BITS, O>,
+    index: usize,
+}
+
+impl<S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder> Iterator
+    for PackedIterator<'_, S, E, BITS, O>
+{
+    type Item = E;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index < self.buffer.len() {
+            let result = Some(self.buffer.get(self.index));
+            self.index += 1;
+            result
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    IntoIterator for &'a PackedBuffer<S, E, BITS, O>
+{
+    type Item = E;
+    type IntoIter = PackedIterator<'a, S, E, BITS, O>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        PackedIterator {
+            buffer: self,
+            index: 0,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! RGB values the panel colors are shown as.
+//!
+//! The physical Spectra 6 inks are much duller than the sRGB primaries, so matching images
+//! against [`Palette::IDEAL`] picks poor colors. [`Palette::SPECTRA6`] holds measured values,
+//! and measurements of a particular panel go into [`Palette::new`]. Every palette works at
+//! runtime: [`Palette::to_rgb888`] for previews, [`Palette::nearest`] and
+//! [`quantize_with`](crate::quantize::quantize_with) for single colors,
+//! [`QuantizeTable::new`](crate::quantize::QuantizeTable::new) for table lookups and
+//! [`Ditherer::with_palette`](crate::dither::Ditherer::with_palette) for images.
+//!
+//! [`Palette::DEFAULT`] is the ideal palette, or the measured one with the
+//! `calibrated-palette` feature. It is used by
+//! [`AsRgbColor`](crate::display::AsRgbColor), the conversions between [`E6Color`] and
+//! `Rgb888`, [`quantize`](crate::quantize::quantize) and [`Ditherer`](crate::dither::Ditherer)
+//! unless they are given another one.
+
+use crate::display::RgbColor as DisplayRgbColor;
+use crate::display::{Error, InvalidColor};
+use crate::e6_display::E6Color;
+use crate::quantize::{Distance, nearest_oklab, nearest_weighted_rgb, oklab};
+use defmt::Format;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::RgbColor;
+
+/// Colors in the order of the palette entries.
+pub(crate) const COLORS: [E6Color; 6] = [
+    E6Color::Black,
+    E6Color::White,
+    E6Color::Yellow,
+    E6Color::Red,
+    E6Color::Blue,
+    E6Color::Green,
+];
+
+#[derive(Format, Copy, Clone, PartialEq, Debug)]
+pub struct Palette {
+    rgb: [DisplayRgbColor; 6],
+    oklab: [[f32; 3]; 6],
+}
+
+impl Palette {
+    /// The sRGB primaries and their mixes.
+    pub const IDEAL: Palette = Palette::new([
+        (0, 0, 0),
+        (255, 255, 255),
+        (255, 255, 0),
+        (255, 0, 0),
+        (0, 0, 255),
+        (0, 255, 0),
+    ]);
+
+    /// Colors measured on a Spectra 6 panel.
+    pub const SPECTRA6: Palette = Palette::new([
+        (25, 30, 33),
+        (232, 232, 232),
+        (239, 222, 68),
+        (178, 19, 24),
+        (33, 87, 186),
+        (18, 95, 32),
+    ]);
+
+    /// Palette used by the conversions, quantization and dithering unless they are given
+    /// another one.
+    #[cfg(not(feature = "calibrated-palette"))]
+    pub const DEFAULT: Palette = Palette::IDEAL;
+    #[cfg(feature = "calibrated-palette")]
+    pub const DEFAULT: Palette = Palette::SPECTRA6;
+
+    /// RGB values of black, white, yellow, red, blue and green, in this order.
+    pub const fn new(rgb: [DisplayRgbColor; 6]) -> Self {
+        let mut lab = [[0.0; 3]; 6];
+        let mut entry = 0;
+        while entry < rgb.len() {
+            let (r, g, b) = rgb[entry];
+            lab[entry] = oklab([r, g, b]);
+            entry += 1;
+        }
+        Self { rgb, oklab: lab }
+    }
+
+    /// RGB value the panel shows the color as.
+    pub const fn rgb(&self, color: E6Color) -> DisplayRgbColor {
+        self.rgb[Self::entry(color)]
+    }
+
+    pub fn to_rgb888(&self, color: E6Color) -> Rgb888 {
+        let (r, g, b) = self.rgb(color);
+        Rgb888::new(r, g, b)
+    }
+
+    /// Color whose RGB value in this palette is exactly `color`.
+    pub fn try_color(&self, color: Rgb888) -> Result<E6Color, Error> {
+        let rgb = (color.r(), color.g(), color.b());
+        self.rgb
+            .iter()
+            .position(|entry| *entry == rgb)
+            .map(|entry| COLORS[entry])
+            .ok_or(Error::InvalidColor(InvalidColor::Rgb(rgb.0, rgb.1, rgb.2)))
+    }
+
+    /// Palette color closest to `color` with the given distance.
+    pub fn nearest(&self, color: impl Into<Rgb888>, distance: Distance) -> E6Color {
+        let color = color.into();
+        let rgb = [color.r(), color.g(), color.b()];
+        let entry = match distance {
+            Distance::OkLab => nearest_oklab(&self.oklab, rgb),
+            Distance::WeightedRgb => nearest_weighted_rgb(&self.rgb, rgb),
+        };
+        COLORS[entry]
+    }
+
+    /// Colors with their RGB values, in the order of the palette entries.
+    pub(crate) const fn entries(&self) -> [(E6Color, DisplayRgbColor); 6] {
+        let mut entries = [(E6Color::Black, (0, 0, 0)); 6];
+        let mut entry = 0;
+        while entry < COLORS.len() {
+            entries[entry] = (COLORS[entry], self.rgb[entry]);
+            entry += 1;
+        }
+        entries
+    }
+
+    pub(crate) const fn oklab_entries(&self) -> &[[f32; 3]; 6] {
+        &self.oklab
+    }
+
+    const fn entry(color: E6Color) -> usize {
+        match color {
+            E6Color::Black => 0,
+            E6Color::White => 1,
+            E6Color::Yellow => 2,
+            E6Color::Red => 3,
+            E6Color::Blue => 4,
+            E6Color::Green => 5,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -0,0 +1,146 @@
+use crate::busy::BusyPolicy;
+use crate::e6_display::CommandCode;
+
+pub const DEFAULT_RESET_DELAY_MS: u32 = 30;
+pub const DEFAULT_BUSY_POLL_INTERVAL_MS: u32 = 100;
+pub const DEFAULT_BUSY_TIMEOUT_MS: u32 = 20_000;
+
+/// Init registers shared by the 4" and 7.3" single-controller panels.
+const SPECTRA6_REGISTERS: &[(CommandCode, &[u8])] = &[
+    (CommandCode::INIT, &[0x49, 0x55, 0x20, 0x08, 0x09, 0x18]),
+    (CommandCode::PWR, &[0x3F]),
+    (CommandCode::PSR, &[0x5F, 0x69]),
+    (CommandCode::BTST1, &[0x40, 0x1F, 0x1F, 0x2C]),
+    (CommandCode::BTST3, &[0x6F, 0x1F, 0x1F, 0x22]),
+    (CommandCode::BTST2, &[0x6F, 0x1F, 0x17, 0x17]),
+    (CommandCode::POFS, &[0x00, 0x54, 0x00, 0x44]),
+    (CommandCode::TCON, &[0x02, 0x00]),
+    (CommandCode::PLL, &[0x08]),
+    (CommandCode::CDI, &[0x3F]),
+    (CommandCode::PWS, &[0x2F]),
+    (CommandCode::VDCS, &[0x01]),
+];
+
+const SPECTRA6_13IN3_REGISTERS: &[(CommandCode, &[u8])] = &[
+    (
+        CommandCode::AN_TM,
+        &[0xC0, 0x1C, 0x1C, 0xCC, 0xCC, 0xCC, 0x15, 0x15, 0x55],
+    ),
+    (CommandCode::CMD66, &[0x49, 0x55, 0x13, 0x5D, 0x05, 0x10]),
+    (CommandCode::PSR, &[0xDF, 0x69]),
+    (CommandCode::CDI, &[0xF7]),
+    (CommandCode::TCON, &[0x03, 0x03]),
+    (CommandCode::AGID, &[0x10]),
+    (CommandCode::PWS, &[0x22]),
+    (CommandCode::CCSET, &[0x01]),
+];
+
+const SPECTRA6_13IN3_PRIMARY_REGISTERS: &[(CommandCode, &[u8])] = &[
+    (CommandCode::PWR, &[0x0F, 0x00, 0x28, 0x2C, 0x28, 0x38]),
+    (CommandCode::EN_BUF, &[0x07]),
+    (CommandCode::BTST2, &[0xE8, 0x28]),
+    (CommandCode::BOOST_VDDP_EN, &[0x01]),
+    (CommandCode::BTST1, &[0xE8, 0x28]),
+    (CommandCode::BUCK_BOOST_VDDN, &[0x01]),
+    (CommandCode::TFT_VCOM_POWER, &[0x02]),
+];
+
+/// Resolution, init register values and timings of a Spectra 6 panel.
+///
+/// TRES is not part of the register lists, the driver derives it from `width` and `height`
+/// and sends it after them, unless `tres` overrides it.
+#[derive(Copy, Clone, Debug)]
+pub struct PanelProfile {
+    pub width: u16,
+    pub height: u16,
+    /// Registers sent to every controller after reset.
+    pub registers: &'static [(CommandCode, &'static [u8])],
+    /// Registers sent after `registers` only to the controller that owns the power circuits.
+    /// Single-controller panels send them to their only controller.
+    pub primary_registers: &'static [(CommandCode, &'static [u8])],
+    /// TRES payload sent to every controller instead of the derived one.
+    pub tres: Option<[u8; 4]>,
+    /// How long the reset line is held low, and how long to wait after releasing it.
+    pub reset_delay_ms: u32,
+    pub busy_policy: BusyPolicy,
+}
+
+impl PanelProfile {
+    /// 4" 400x600 panel.
+    pub const SPECTRA6_4IN0: Self = Self::new(400, 600, SPECTRA6_REGISTERS);
+
+    /// 7.3" 800x480 panel.
+    pub const SPECTRA6_7IN3: Self = Self::new(800, 480, SPECTRA6_REGISTERS);
+
+    /// 13.3" 1200x1600 panel driven by two controllers. Both get the vendor TRES of 1200x800
+    /// rather than their 600x1600 half.
+    pub const SPECTRA6_13IN3: Self = Self {
+        primary_registers: SPECTRA6_13IN3_PRIMARY_REGISTERS,
+        tres: Some([0x04, 0xB0, 0x03, 0x20]),
+        busy_policy: BusyPolicy::new(DEFAULT_BUSY_POLL_INTERVAL_MS, 40_000),
+        ..Self::new(1200, 1600, SPECTRA6_13IN3_REGISTERS)
+    };
+
+    /// Custom profile with the default reset and busy timings.
+    pub const fn new(
+        width: u16,
+        height: u16,
+        registers: &'static [(CommandCode, &'static [u8])],
+    ) -> Self {
+        Self {
+            width,
+            height,
+            registers,
+            primary_registers: &[],
+            tres: None,
+            reset_delay_ms: DEFAULT_RESET_DELAY_MS,
+            busy_policy: BusyPolicy::new(DEFAULT_BUSY_POLL_INTERVAL_MS, DEFAULT_BUSY_TIMEOUT_MS),
+        }
+    }
+
+    /// Changes the resolution. TRES is derived from it again, dropping any override.
+    pub const fn with_resolution(self, width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            tres: None,
+            ..self
+        }
+    }
+
+    pub const fn with_tres(self, tres: [u8; 4]) -> Self {
+        Self {
+            tres: Some(tres),
+            ..self
+        }
+    }
+
+    pub const fn with_reset_delay_ms(self, reset_delay_ms: u32) -> Self {
+        Self {
+            reset_delay_ms,
+            ..self
+        }
+    }
+
+    pub const fn with_busy_policy(self, busy_policy: BusyPolicy) -> Self {
+        Self {
+            busy_policy,
+            ..self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// TRES payload for the configured resolution.
+    pub fn resolution_data(&self) -> [u8; 4] {
+        let [width_high, width_low] = self.width.to_be_bytes();
+        let [height_high, height_low] = self.height.to_be_bytes();
+        [width_high, width_low, height_high, height_low]
+    }
+}
@@ -0,0 +1,372 @@
+//! Transport-agnostic command sequencing of the Spectra 6 controllers of a panel.
+//!
+//! [`Protocol`] keeps the controller state and turns every driver operation into a
+//! [`Sequence`] of [`Step`]s. The drivers only execute the steps with their transport, pins
+//! and delay source, so the blocking and async drivers send exactly the same commands.
+//! Panels split between two controllers get [`Step::Select`] steps in front of the commands
+//! of each controller, while the reset and busy waits on the shared lines happen once.
+
+use crate::busy::BusyPhase;
+use crate::display::{Controller, Error, PowerState};
+use crate::e6_display::{CommandCode, PartialWindow};
+use crate::profile::PanelProfile;
+
+pub(crate) const DEEP_SLEEP_CHECK_CODE: u8 = 0xA5;
+
+/// Single action a driver has to perform.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum Step<'a> {
+    /// Drives the reset line, `true` holds the controller in reset.
+    Reset(bool),
+    DelayMs(u32),
+    /// Sends the following commands and data to the controller. Only panels with two
+    /// controllers get this step.
+    Select(Controller),
+    Command(CommandCode),
+    /// Parameters of the last command.
+    Data(&'a [u8]),
+    /// Sends the command while reading back the status byte of the controller.
+    CommandWithStatus(CommandCode),
+    /// Pixels of the window, row by row, as the parameters of the last command. The window is
+    /// in panel coordinates.
+    FrameBuffer(PartialWindow),
+    WaitBusy(BusyPhase),
+}
+
+/// Driver operation a [`Sequence`] is created for.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum Operation {
+    Initialize,
+    Refresh,
+    PartialRefresh(PartialWindow),
+    Sleep,
+}
+
+/// Group of steps with a common purpose.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Block {
+    Reset,
+    Select(Controller),
+    Registers(&'static [(CommandCode, &'static [u8])]),
+    /// TRES override of the profile, or the part of the panel driven by one controller.
+    Resolution,
+    /// PTL with the window in the coordinates of the controller.
+    Window(Controller, PartialWindow),
+    Transfer(PartialWindow),
+    /// Sends the command to the marked controllers and waits until all of them are done.
+    Broadcast {
+        command: CommandCode,
+        data: &'static [u8],
+        controllers: [bool; 2],
+        phase: BusyPhase,
+    },
+    DeepSleep,
+    /// Records the power state the blocks before put the controllers in.
+    Enter(PowerState),
+}
+
+/// Init of two controllers, a transfer to each of them and the power cycle.
+const MAX_BLOCKS: usize = 19;
+
+/// Controller state shared by the drivers.
+pub(crate) struct Protocol {
+    pub(crate) profile: PanelProfile,
+    /// Controllers side by side, the primary one on the left.
+    controllers: &'static [Controller],
+    power_state: PowerState,
+    /// Whether each controller has a partial window programmed.
+    partial_window_active: [bool; 2],
+}
+
+impl Protocol {
+    pub(crate) fn new(profile: PanelProfile) -> Self {
+        Self::with_controllers(profile, &[Controller::Primary])
+    }
+
+    /// Protocol of a panel whose rows are split in halves between two controllers.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn dual(profile: PanelProfile) -> Self {
+        Self::with_controllers(profile, &[Controller::Primary, Controller::Secondary])
+    }
+
+    fn with_controllers(profile: PanelProfile, controllers: &'static [Controller]) -> Self {
+        Self {
+            profile,
+            controllers,
+            power_state: PowerState::Uninitialized,
+            partial_window_active: [false; 2],
+        }
+    }
+
+    pub(crate) fn power_state(&self) -> PowerState {
+        self.power_state
+    }
+
+    fn is_dual(&self) -> bool {
+        self.controllers.len() > 1
+    }
+
+    /// Width of the part of the panel driven by one controller.
+    fn controller_width(&self) -> u16 {
+        self.profile.width / self.controllers.len() as u16
+    }
+
+    /// The whole area of one controller, in its own coordinates.
+    fn controller_window(&self) -> PartialWindow {
+        PartialWindow::full(self.controller_width(), self.profile.height)
+    }
+
+    /// First panel column driven by the controller.
+    fn column_offset(&self, controller: Controller) -> u16 {
+        match controller {
+            Controller::Primary => 0,
+            Controller::Secondary => self.controller_width(),
+        }
+    }
+
+    /// Steps of the operation. Refreshes of a sleeping panel wake it up first.
+    pub(crate) fn sequence(&mut self, operation: Operation) -> Result<Sequence<'_>, Error> {
+        let mut blocks = [None; MAX_BLOCKS];
+        let mut len = 0;
+        let mut push = |block: Block| {
+            blocks[len] = Some(block);
+            len += 1;
+        };
+        match operation {
+            Operation::Initialize => {
+                defmt::info!("Initialize display");
+                self.init_blocks(&mut push);
+            }
+            Operation::Refresh | Operation::PartialRefresh(_) => {
+                self.check_initialized()?;
+                let sleeping = self.power_state == PowerState::DeepSleep;
+                if sleeping {
+                    defmt::info!("Wake up from deep sleep");
+                    self.init_blocks(&mut push);
+                }
+                let window = match operation {
+                    Operation::PartialRefresh(window) => window,
+                    _ => PartialWindow::full(self.profile.width, self.profile.height),
+                };
+                let mut refreshed = [false; 2];
+                for &controller in self.controllers {
+                    let offset = self.column_offset(controller);
+                    let end = offset + self.controller_width() - 1;
+                    let Some(part) = window.clip_columns(offset, end) else {
+                        continue;
+                    };
+                    let local = part.shift_left(offset);
+                    if self.is_dual() {
+                        push(Block::Select(controller));
+                    }
+                    // A reset clears the window, otherwise only a partial one has to be replaced.
+                    if local != self.controller_window()
+                        || (self.partial_window_active[index(controller)] && !sleeping)
+                    {
+                        push(Block::Window(controller, local));
+                    }
+                    push(Block::Transfer(part));
+                    refreshed[index(controller)] = true;
+                }
+                push(Block::Broadcast {
+                    command: CommandCode::PON,
+                    data: &[],
+                    controllers: [true; 2],
+                    phase: BusyPhase::PowerOn,
+                });
+                push(Block::Broadcast {
+                    command: CommandCode::DRF,
+                    data: &[0x00],
+                    controllers: refreshed,
+                    phase: BusyPhase::Refresh,
+                });
+                push(Block::Broadcast {
+                    command: CommandCode::POF,
+                    data: &[],
+                    controllers: [true; 2],
+                    phase: BusyPhase::PowerOff,
+                });
+            }
+            Operation::Sleep => {
+                self.check_initialized()?;
+                if self.power_state != PowerState::DeepSleep {
+                    defmt::info!("Enter deep sleep");
+                    for &controller in self.controllers {
+                        if self.is_dual() {
+                            push(Block::Select(controller));
+                        }
+                        push(Block::DeepSleep);
+                    }
+                    push(Block::Enter(PowerState::DeepSleep));
+                }
+            }
+        }
+        Ok(Sequence {
+            protocol: self,
+            blocks,
+            block: 0,
+            step: 0,
+            scratch: [0; 9],
+        })
+    }
+
+    /// Resets the controllers and configures every one of them. The registers of the power
+    /// circuits come last on a split panel, once both controllers know their resolution.
+    fn init_blocks(&self, push: &mut impl FnMut(Block)) {
+        push(Block::Reset);
+        if self.is_dual() {
+            for &controller in self.controllers {
+                push(Block::Select(controller));
+                push(Block::Registers(self.profile.registers));
+                push(Block::Resolution);
+            }
+            push(Block::Select(Controller::Primary));
+            push(Block::Registers(self.profile.primary_registers));
+        } else {
+            push(Block::Registers(self.profile.registers));
+            push(Block::Registers(self.profile.primary_registers));
+            push(Block::Resolution);
+        }
+        push(Block::Enter(PowerState::Awake));
+    }
+
+    fn check_initialized(&self) -> Result<(), Error> {
+        if self.power_state == PowerState::Uninitialized {
+            return Err(Error::NotInitialized);
+        }
+        Ok(())
+    }
+}
+
+/// Position of the controller's state in the per-controller arrays.
+fn index(controller: Controller) -> usize {
+    match controller {
+        Controller::Primary => 0,
+        Controller::Secondary => 1,
+    }
+}
+
+/// Next step to yield, with parameters in the scratch buffer referred to by length.
+enum Next {
+    Step(Step<'static>),
+    Scratch(usize),
+}
+
+/// Steps of one operation, produced one at a time.
+///
+/// The controller state in [`Protocol`] is updated as the steps are taken, so a sequence that
+/// is abandoned after a failed step leaves the state of the last completed block.
+pub(crate) struct Sequence<'a> {
+    protocol: &'a mut Protocol,
+    blocks: [Option<Block>; MAX_BLOCKS],
+    block: usize,
+    step: usize,
+    scratch: [u8; 9],
+}
+
+impl Sequence<'_> {
+    #[allow(clippy::should_implement_trait)]
+    pub(crate) fn next(&mut self) -> Option<Step<'_>> {
+        let next = loop {
+            let block = (*self.blocks.get(self.block)?)?;
+            if self.step == 0 {
+                self.begin(block);
+            }
+            match self.block_step(block, self.step) {
+                Some(next) => {
+                    self.step += 1;
+                    break next;
+                }
+                None => {
+                    self.block += 1;
+                    self.step = 0;
+                }
+            }
+        };
+        Some(match next {
+            Next::Step(step) => step,
+            Next::Scratch(len) => Step::Data(&self.scratch[..len]),
+        })
+    }
+
+    fn begin(&mut self, block: Block) {
+        match block {
+            Block::Reset => {
+                self.protocol.power_state = PowerState::Uninitialized;
+                self.protocol.partial_window_active = [false; 2];
+            }
+            Block::Window(controller, window) => {
+                self.protocol.partial_window_active[index(controller)] =
+                    window != self.protocol.controller_window();
+            }
+            Block::Enter(power_state) => self.protocol.power_state = power_state,
+            _ => {}
+        }
+    }
+
+    fn block_step(&mut self, block: Block, step: usize) -> Option<Next> {
+        let reset_delay_ms = self.protocol.profile.reset_delay_ms;
+        Some(Next::Step(match (block, step) {
+            (Block::Reset, 0) => Step::Reset(true),
+            (Block::Reset, 1) => Step::DelayMs(reset_delay_ms),
+            (Block::Reset, 2) => Step::Reset(false),
+            (Block::Reset, 3) => Step::DelayMs(reset_delay_ms),
+            (Block::Reset, 4) => Step::WaitBusy(BusyPhase::Reset),
+            (Block::Registers(registers), step) => {
+                let (command, data) = registers.get(step / 2)?;
+                if step % 2 == 0 {
+                    Step::Command(*command)
+                } else {
+                    Step::Data(data)
+                }
+            }
+            (Block::Select(controller), 0) => Step::Select(controller),
+            (Block::Resolution, 0) => Step::Command(CommandCode::TRES),
+            (Block::Resolution, 1) => {
+                let profile = self.protocol.profile;
+                let resolution = profile.tres.unwrap_or_else(|| {
+                    profile
+                        .with_resolution(self.protocol.controller_width(), profile.height)
+                        .resolution_data()
+                });
+                self.scratch[..4].copy_from_slice(&resolution);
+                return Some(Next::Scratch(4));
+            }
+            (Block::Window(..), 0) => Step::Command(CommandCode::PTL),
+            (Block::Window(_, window), 1) => {
+                self.scratch = window.command_data();
+                return Some(Next::Scratch(9));
+            }
+            (Block::Transfer(_), 0) => Step::Command(CommandCode::DTM1),
+            (Block::Transfer(window), 1) => Step::FrameBuffer(window),
+            (Block::Transfer(_), 2) => Step::CommandWithStatus(CommandCode::DSP),
+            (Block::Transfer(_), 3) => Step::WaitBusy(BusyPhase::DataTransfer),
+            (
+                Block::Broadcast {
+                    command,
+                    data,
+                    controllers,
+                    phase,
+                },
+                step,
+            ) => {
+                let all = self.protocol.controllers;
+                let targets = || all.iter().filter(|target| controllers[index(**target)]);
+                let select = self.protocol.is_dual() as usize;
+                let per_controller = select + 1 + !data.is_empty() as usize;
+                match (targets().nth(step / per_controller), step % per_controller) {
+                    (Some(target), part) if part < select => Step::Select(*target),
+                    (Some(_), part) if part == select => Step::Command(command),
+                    (Some(_), _) => Step::Data(data),
+                    (None, 0) if step == targets().count() * per_controller => {
+                        Step::WaitBusy(phase)
+                    }
+                    _ => return None,
+                }
+            }
+            (Block::DeepSleep, 0) => Step::Command(CommandCode::DSLP),
+            (Block::DeepSleep, 1) => Step::Data(&[DEEP_SLEEP_CHECK_CODE]),
+            _ => return None,
+        }))
+    }
+}
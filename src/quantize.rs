@@ -0,0 +1,203 @@
+//! Nearest-color mapping of any embedded-graphics color to the six colors of the panel.
+//!
+//! A [`QuantizeTable`] holds the palette color closest in OKLab for 16 levels per channel. It
+//! is built by a `const fn`, so tables of custom palettes can live in flash too, needs no
+//! floating point at runtime and 2 KiB of memory, which suits MCUs without an FPU.
+//! [`quantize`] uses the table of the default [`Palette`]. [`quantize_with`] and [`nearest`]
+//! compare the exact color with a palette instead.
+//!
+//! The `From` conversions of the embedded-graphics RGB and grayscale colors into [`E6Color`]
+//! use [`quantize`], so colors outside of the palette never panic.
+
+use crate::display::RgbColor as DisplayRgbColor;
+use crate::e6_display::E6Color;
+use crate::palette::{COLORS, Palette};
+use defmt::Format;
+use embedded_graphics::pixelcolor::{
+    Bgr555, Bgr565, Bgr666, Bgr888, Gray2, Gray4, Gray8, Rgb555, Rgb565, Rgb666, Rgb888,
+};
+use embedded_graphics::prelude::RgbColor;
+
+#[derive(Format, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Distance {
+    /// Euclidean distance in the OKLab color space, the closest to perceived differences.
+    /// Uses `f32` math, which is emulated in software on MCUs without an FPU.
+    OkLab,
+    /// Integer RGB distance weighted by the mean red value ("redmean").
+    WeightedRgb,
+}
+
+/// Palette colors closest in OKLab to the colors of a 16x16x16 grid.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QuantizeTable {
+    /// Palette entries of the cell centers, two per byte with the even index in the low nibble.
+    entries: [u8; 2048],
+}
+
+impl QuantizeTable {
+    /// Table of the palette. Expensive, best evaluated in a `const` or `static`.
+    pub const fn new(palette: &Palette) -> Self {
+        let mut entries = [0; 2048];
+        let mut index = 0;
+        while index < 4096 {
+            let r = (index >> 8) as u8 * 16 + 8;
+            let g = ((index >> 4) & 0x0F) as u8 * 16 + 8;
+            let b = (index & 0x0F) as u8 * 16 + 8;
+            let entry = nearest_oklab(palette.oklab_entries(), [r, g, b]) as u8;
+            entries[index / 2] |= entry << (index % 2 * 4);
+            index += 1;
+        }
+        Self { entries }
+    }
+
+    /// Palette color closest to `color`, looked up at the center of its cell.
+    pub fn quantize(&self, color: impl Into<Rgb888>) -> E6Color {
+        let color = color.into();
+        let index = (color.r() as usize >> 4) << 8
+            | (color.g() as usize >> 4) << 4
+            | color.b() as usize >> 4;
+        let entry = (self.entries[index / 2] >> (index % 2 * 4)) & 0x0F;
+        COLORS[entry as usize]
+    }
+}
+
+static DEFAULT_TABLE: QuantizeTable = QuantizeTable::new(&Palette::DEFAULT);
+
+/// Color of the default palette closest to `color` in OKLab, looked up in a table with 16
+/// levels per channel.
+pub fn quantize(color: impl Into<Rgb888>) -> E6Color {
+    DEFAULT_TABLE.quantize(color)
+}
+
+/// Color of `palette` closest to `color` in OKLab, computed without a table. A
+/// [`QuantizeTable`] is faster for many colors.
+pub fn quantize_with(palette: &Palette, color: impl Into<Rgb888>) -> E6Color {
+    palette.nearest(color, Distance::OkLab)
+}
+
+/// Palette color closest to `color` with the given distance, see [`Palette::nearest`].
+pub fn nearest(color: impl Into<Rgb888>, distance: Distance) -> E6Color {
+    Palette::DEFAULT.nearest(color, distance)
+}
+
+/// Palette entry closest to the color in OKLab.
+pub(crate) const fn nearest_oklab(palette: &[[f32; 3]; 6], rgb: [u8; 3]) -> usize {
+    let lab = oklab(rgb);
+    let mut best = 0;
+    let mut best_distance = f32::MAX;
+    let mut entry = 0;
+    while entry < palette.len() {
+        let mut distance = 0.0;
+        let mut channel = 0;
+        while channel < 3 {
+            let difference = lab[channel] - palette[entry][channel];
+            distance += difference * difference;
+            channel += 1;
+        }
+        if distance < best_distance {
+            best = entry;
+            best_distance = distance;
+        }
+        entry += 1;
+    }
+    best
+}
+
+/// Palette entry closest to the color with the redmean weighted RGB distance.
+pub(crate) fn nearest_weighted_rgb(palette: &[DisplayRgbColor; 6], rgb: [u8; 3]) -> usize {
+    let [r, g, b] = rgb.map(|channel| channel as i32);
+    let mut best = 0;
+    let mut best_distance = i32::MAX;
+    for (entry, (palette_r, palette_g, palette_b)) in palette.iter().enumerate() {
+        let red_mean = (r + *palette_r as i32) / 2;
+        let (dr, dg, db) = (
+            r - *palette_r as i32,
+            g - *palette_g as i32,
+            b - *palette_b as i32,
+        );
+        let distance =
+            (((512 + red_mean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - red_mean) * db * db) >> 8);
+        if distance < best_distance {
+            best = entry;
+            best_distance = distance;
+        }
+    }
+    best
+}
+
+/// sRGB channel values in linear light.
+const LINEAR: [f32; 256] = {
+    let mut linear = [0.0; 256];
+    let mut value = 0;
+    while value < 256 {
+        let c = value as f32 / 255.0;
+        linear[value] = if c <= 0.04045 {
+            c / 12.92
+        } else {
+            // c^2.4 as c^2 * c^0.4
+            let base = (c + 0.055) / 1.055;
+            let fifth = root(base, 5);
+            base * base * fifth * fifth
+        };
+        value += 1;
+    }
+    linear
+};
+
+pub(crate) const fn oklab(rgb: [u8; 3]) -> [f32; 3] {
+    let r = LINEAR[rgb[0] as usize];
+    let g = LINEAR[rgb[1] as usize];
+    let b = LINEAR[rgb[2] as usize];
+    let l = root(0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b, 3);
+    let m = root(0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b, 3);
+    let s = root(0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b, 3);
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// `n`-th root of `x` in `0.0..=1.0` by Newton's method, as `core` has no `powf` or `cbrt`.
+const fn root(x: f32, n: i32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut iteration = 0;
+    while iteration < 40 {
+        let mut power = 1.0;
+        let mut exponent = 1;
+        while exponent < n {
+            power *= y;
+            exponent += 1;
+        }
+        y = ((n - 1) as f32 * y + x / power) / n as f32;
+        iteration += 1;
+    }
+    y
+}
+
+/// Nearest palette color, see [`quantize`].
+impl From<Rgb888> for E6Color {
+    fn from(value: Rgb888) -> Self {
+        quantize(value)
+    }
+}
+
+macro_rules! impl_quantize_from {
+    ($($color:ident),+) => {
+        $(
+            /// Nearest palette color, see [`quantize`].
+            impl From<$color> for E6Color {
+                fn from(value: $color) -> Self {
+                    quantize(value)
+                }
+            }
+        )+
+    };
+}
+
+impl_quantize_from!(
+    Rgb555, Bgr555, Rgb565, Bgr565, Rgb666, Bgr666, Bgr888, Gray2, Gray4, Gray8
+);
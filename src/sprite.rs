@@ -0,0 +1,112 @@
+//! Images composited into grids and the frame buffer.
+//!
+//! A [`Sprite`] is a [`GridView`] of an icon or pre-rendered widget, optionally with a color
+//! key or a 1-bit mask for transparent pixels. [`Grid::blit`](crate::grid::Grid::blit),
+//! [`GridViewMut::blit`](crate::grid::GridViewMut::blit) and the `blit` methods of the drivers
+//! draw it clipped to the target. Rows of opaque sprites are copied a byte at a time when they
+//! start at the same position within a byte on both sides.
+
+use crate::grid::GridView;
+use crate::packed::{BitOrder, MsbFirst, PackedBuffer};
+
+pub struct Sprite<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    M: AsMut<[u8]> + AsRef<[u8]> = [u8; 0],
+    const BITS: usize = 4,
+    O: BitOrder = MsbFirst,
+> {
+    image: GridView<'a, S, E, BITS, O>,
+    key: Option<u8>,
+    mask: Option<GridView<'a, M, u8, 1>>,
+}
+
+impl<'a, S: AsMut<[u8]> + AsRef<[u8]>, E: Into<u8> + From<u8>, const BITS: usize, O: BitOrder>
+    Sprite<'a, S, E, [u8; 0], BITS, O>
+{
+    /// Sprite drawing every pixel of `image`.
+    pub fn new(image: GridView<'a, S, E, BITS, O>) -> Self {
+        Self {
+            image,
+            key: None,
+            mask: None,
+        }
+    }
+}
+
+impl<
+    'a,
+    S: AsMut<[u8]> + AsRef<[u8]>,
+    E: Into<u8> + From<u8>,
+    M: AsMut<[u8]> + AsRef<[u8]>,
+    const BITS: usize,
+    O: BitOrder,
+> Sprite<'a, S, E, M, BITS, O>
+{
+    /// Leaves out the pixels of the given color.
+    pub fn with_color_key(self, key: E) -> Self {
+        Self {
+            key: Some(key.into()),
+            ..self
+        }
+    }
+
+    /// Leaves out the pixels whose mask bit is 0, and those outside of the mask.
+    pub fn with_mask<N: AsMut<[u8]> + AsRef<[u8]>>(
+        self,
+        mask: GridView<'a, N, u8, 1>,
+    ) -> Sprite<'a, S, E, N, BITS, O> {
+        Sprite {
+            image: self.image,
+            key: self.key,
+            mask: Some(mask),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.image.height()
+    }
+
+    /// Bits of the pixel, or `None` when it is transparent.
+    pub(crate) fn pixel_bits(&self, x: usize, y: usize) -> Option<u8> {
+        let bits = self.image.buffer().get_bits(self.image.index(x, y)?);
+        if self.key == Some(bits) {
+            return None;
+        }
+        if let Some(mask) = &self.mask {
+            let visible = mask
+                .index(x, y)
+                .is_some_and(|index| mask.buffer().get_bits(index) != 0);
+            if !visible {
+                return None;
+            }
+        }
+        Some(bits)
+    }
+
+    /// Draws `count` pixels of row `y` from column `x` on to `target` from `start` on.
+    pub(crate) fn blit_row<T: AsMut<[u8]> + AsRef<[u8]>>(
+        &self,
+        target: &mut PackedBuffer<T, E, BITS, O>,
+        start: usize,
+        (x, y): (usize, usize),
+        count: usize,
+    ) {
+        if self.key.is_none() && self.mask.is_none() {
+            if let Some(source) = self.image.index(x, y) {
+                target.copy_from(start, self.image.buffer(), source, count);
+            }
+            return;
+        }
+        for offset in 0..count {
+            if let Some(bits) = self.pixel_bits(x + offset, y) {
+                target.set_bits(start + offset, bits);
+            }
+        }
+    }
+}
@@ -0,0 +1,711 @@
+//! Host-side emulator of a Spectra 6 controller.
+//!
+//! [`Emulator`] hands out SPI, DC, RST, BUSY and delay handles that can be passed to
+//! [`E6Display`](crate::e6_display::E6Display) or `AsyncE6Display` in place of real
+//! peripherals. The emulator decodes the command stream, keeps the controller RAM and the
+//! displayed image, and drives the BUSY line from a virtual clock that only advances
+//! through the delay handle, so tests run instantly and deterministically.
+
+extern crate std;
+
+use crate::e6_display::{CommandCode, E6Color};
+use crate::nibbles::underlying_data_len;
+use core::convert::Infallible;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{ErrorType as DigitalErrorType, InputPin, OutputPin};
+use embedded_hal::spi::{ErrorType as SpiErrorType, Operation, SpiDevice};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::vec::Vec;
+
+/// Busy durations reported by the emulated controller, in milliseconds.
+#[derive(Copy, Clone, Debug)]
+pub struct EmulatorTimings {
+    pub reset_ms: u32,
+    pub power_on_ms: u32,
+    pub refresh_ms: u32,
+    pub power_off_ms: u32,
+}
+
+impl Default for EmulatorTimings {
+    fn default() -> Self {
+        Self {
+            reset_ms: 20,
+            power_on_ms: 80,
+            refresh_ms: 12_000,
+            power_off_ms: 40,
+        }
+    }
+}
+
+/// A command received by the controller together with all data bytes sent after it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandRecord {
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+impl CommandRecord {
+    pub fn is(&self, command: CommandCode) -> bool {
+        self.command == command as u8
+    }
+}
+
+/// Protocol misuse detected by the emulator.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// A byte was sent while the BUSY line was low.
+    WriteWhileBusy { command: u8 },
+    /// Data was sent before any command.
+    DataWithoutCommand,
+    /// Display refresh was requested while the panel power was off.
+    RefreshWithoutPower,
+    /// Pixel data was sent before the resolution was configured.
+    DataWithoutResolution,
+    /// A byte was sent while the controller was in deep sleep and got ignored.
+    WriteWhileSleeping,
+    /// The partial window does not start and end on byte boundaries.
+    UnalignedWindow(Window),
+}
+
+/// Inclusive partial window programmed with PTL, in panel coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Window {
+    pub x_start: u16,
+    pub x_end: u16,
+    pub y_start: u16,
+    pub y_end: u16,
+}
+
+/// Snapshot of a single emulated controller.
+#[derive(Clone, Debug)]
+pub struct ControllerState {
+    /// Resolution configured by the last TRES command.
+    pub width: u16,
+    pub height: u16,
+    /// Partial window programmed by the last PTL command, if any since the last reset.
+    pub window: Option<Window>,
+    pub powered: bool,
+    /// Whether the controller is in deep sleep, which only a reset ends.
+    pub sleeping: bool,
+    pub refresh_count: usize,
+    /// All commands received since creation, including those sent during initialization.
+    pub commands: Vec<CommandRecord>,
+}
+
+struct Controller {
+    busy_until_ns: u64,
+    width: u16,
+    height: u16,
+    ram: Vec<u8>,
+    image: Vec<u8>,
+    window: Option<Window>,
+    data_offset: usize,
+    powered: bool,
+    sleeping: bool,
+    refresh_count: usize,
+    log: Vec<CommandRecord>,
+}
+
+impl Controller {
+    fn new() -> Self {
+        Self {
+            busy_until_ns: 0,
+            width: 0,
+            height: 0,
+            ram: Vec::new(),
+            image: Vec::new(),
+            window: None,
+            data_offset: 0,
+            powered: false,
+            sleeping: false,
+            refresh_count: 0,
+            log: Vec::new(),
+        }
+    }
+
+    fn current_window(&self) -> Window {
+        self.window.unwrap_or(Window {
+            x_start: 0,
+            x_end: self.width.saturating_sub(1),
+            y_start: 0,
+            y_end: self.height.saturating_sub(1),
+        })
+    }
+
+    /// Maps the n-th DTM1 byte to its RAM position. Bytes fill the partial window row by
+    /// row, which for the full window is the plain packed frame layout.
+    fn ram_offset(&self, data_offset: usize) -> usize {
+        let window = self.current_window();
+        let row_bytes = (window.x_end as usize - window.x_start as usize) / 2 + 1;
+        let y = window.y_start as usize + data_offset / row_bytes;
+        (y * self.width as usize + window.x_start as usize) / 2 + data_offset % row_bytes
+    }
+
+    fn snapshot(&self) -> ControllerState {
+        ControllerState {
+            width: self.width,
+            height: self.height,
+            window: self.window,
+            powered: self.powered,
+            sleeping: self.sleeping,
+            refresh_count: self.refresh_count,
+            commands: self.log.clone(),
+        }
+    }
+}
+
+struct State {
+    timings: EmulatorTimings,
+    now_ns: u64,
+    data_mode: bool,
+    reset_asserted: bool,
+    controllers: Vec<Controller>,
+    violations: Vec<Violation>,
+    transactions: Vec<Vec<usize>>,
+}
+
+impl State {
+    fn new(controllers: usize) -> Self {
+        Self {
+            timings: EmulatorTimings::default(),
+            now_ns: 0,
+            data_mode: false,
+            reset_asserted: false,
+            controllers: (0..controllers).map(|_| Controller::new()).collect(),
+            violations: Vec::new(),
+            transactions: Vec::new(),
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.reset_asserted
+            || self
+                .controllers
+                .iter()
+                .any(|controller| self.now_ns < controller.busy_until_ns)
+    }
+
+    fn reset(&mut self) {
+        let busy_until_ns = self.now_ns + self.timings.reset_ms as u64 * 1_000_000;
+        for controller in &mut self.controllers {
+            controller.width = 0;
+            controller.height = 0;
+            controller.ram.clear();
+            controller.window = None;
+            controller.data_offset = 0;
+            controller.powered = false;
+            controller.sleeping = false;
+            controller.busy_until_ns = busy_until_ns;
+        }
+    }
+
+    fn write(&mut self, index: usize, bytes: &[u8]) {
+        for byte in bytes {
+            let controller = &self.controllers[index];
+            if controller.sleeping {
+                self.violations.push(Violation::WriteWhileSleeping);
+                continue;
+            }
+            if self.reset_asserted || self.now_ns < controller.busy_until_ns {
+                let command = controller
+                    .log
+                    .last()
+                    .map(|record| record.command)
+                    .unwrap_or(0);
+                self.violations.push(Violation::WriteWhileBusy { command });
+            }
+            if self.data_mode {
+                self.receive_data(index, *byte);
+            } else {
+                self.receive_command(index, *byte);
+            }
+        }
+    }
+
+    fn busy_for(&mut self, index: usize, ms: u32) {
+        self.controllers[index].busy_until_ns = self.now_ns + ms as u64 * 1_000_000;
+    }
+
+    fn receive_command(&mut self, index: usize, command: u8) {
+        let controller = &mut self.controllers[index];
+        controller.log.push(CommandRecord {
+            command,
+            data: Vec::new(),
+        });
+        match command {
+            c if c == CommandCode::DTM1 as u8 => {
+                controller.data_offset = 0;
+                if controller.ram.is_empty() {
+                    self.violations.push(Violation::DataWithoutResolution);
+                }
+            }
+            c if c == CommandCode::PON as u8 => {
+                controller.powered = true;
+                self.busy_for(index, self.timings.power_on_ms);
+            }
+            c if c == CommandCode::POF as u8 => {
+                controller.powered = false;
+                self.busy_for(index, self.timings.power_off_ms);
+            }
+            _ => {}
+        }
+    }
+
+    fn display_refresh(&mut self, index: usize) {
+        let controller = &mut self.controllers[index];
+        if controller.powered {
+            let window = controller.current_window();
+            for y in window.y_start..=window.y_end {
+                let row = y as usize * controller.width as usize;
+                let start = (row + window.x_start as usize) / 2;
+                let end = (row + window.x_end as usize) / 2;
+                if end < controller.ram.len() {
+                    controller.image[start..=end].copy_from_slice(&controller.ram[start..=end]);
+                }
+            }
+            controller.refresh_count += 1;
+            self.busy_for(index, self.timings.refresh_ms);
+        } else {
+            self.violations.push(Violation::RefreshWithoutPower);
+        }
+    }
+
+    fn receive_data(&mut self, index: usize, byte: u8) {
+        let controller = &mut self.controllers[index];
+        let Some(record) = controller.log.last_mut() else {
+            self.violations.push(Violation::DataWithoutCommand);
+            return;
+        };
+        record.data.push(byte);
+        let command = record.command;
+        if command == CommandCode::DTM1 as u8 {
+            let offset = controller.ram_offset(controller.data_offset);
+            if let Some(pair) = controller.ram.get_mut(offset) {
+                *pair = byte;
+            }
+            controller.data_offset += 1;
+        } else if command == CommandCode::PTL as u8 && record.data.len() == 9 {
+            let data = &record.data;
+            let window = Window {
+                x_start: u16::from_be_bytes([data[0], data[1]]),
+                x_end: u16::from_be_bytes([data[2], data[3]]),
+                y_start: u16::from_be_bytes([data[4], data[5]]),
+                y_end: u16::from_be_bytes([data[6], data[7]]),
+            };
+            if !window.x_start.is_multiple_of(2) || window.x_end.is_multiple_of(2) {
+                self.violations.push(Violation::UnalignedWindow(window));
+            }
+            controller.window = Some(window);
+        } else if command == CommandCode::DSLP as u8 && record.data == [0xA5] {
+            controller.powered = false;
+            controller.sleeping = true;
+        } else if command == CommandCode::DRF as u8 && record.data.len() == 1 {
+            self.display_refresh(index);
+        } else if command == CommandCode::TRES as u8 && record.data.len() == 4 {
+            let data = &record.data;
+            controller.width = u16::from_be_bytes([data[0], data[1]]);
+            controller.height = u16::from_be_bytes([data[2], data[3]]);
+            let len = underlying_data_len(controller.width as usize * controller.height as usize);
+            controller.ram.resize(len, 0);
+            if controller.image.len() != len {
+                controller.image = std::vec![0; len];
+            }
+        }
+    }
+
+    /// Finds the controller showing the given panel column. Controllers are placed side by
+    /// side, the first one on the left.
+    fn locate(&self, x: u16) -> Option<(&Controller, u16)> {
+        let mut offset = 0;
+        for controller in &self.controllers {
+            if x < offset + controller.width {
+                return Some((controller, x - offset));
+            }
+            offset += controller.width;
+        }
+        None
+    }
+
+    fn raw_pixel(data: &[u8], width: u16, height: u16, x: u16, y: u16) -> Option<u8> {
+        if x >= width || y >= height {
+            return None;
+        }
+        let index = y as usize * width as usize + x as usize;
+        let pair = *data.get(index / 2)?;
+        Some(if index.is_multiple_of(2) {
+            pair >> 4
+        } else {
+            pair & 0x0F
+        })
+    }
+}
+
+/// Simulated Spectra 6 panel shared by all of its peripheral handles.
+///
+/// A panel has one controller by default. Panels with several controllers share the DC, RST
+/// and BUSY lines, while every controller gets its own SPI device. Their images are placed
+/// side by side, the first controller on the left.
+#[derive(Clone)]
+pub struct Emulator {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self::with_controllers(1)
+    }
+
+    pub fn with_controllers(count: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::new(count))),
+        }
+    }
+
+    pub fn with_timings(self, timings: EmulatorTimings) -> Self {
+        self.state().timings = timings;
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// SPI device of the first controller.
+    pub fn spi(&self) -> EmulatorSpi {
+        self.controller_spi(0)
+    }
+
+    pub fn controller_spi(&self, index: usize) -> EmulatorSpi {
+        assert!(index < self.state().controllers.len(), "Unknown controller");
+        EmulatorSpi {
+            emulator: self.clone(),
+            index,
+            three_wire: false,
+        }
+    }
+
+    /// SPI device of the first controller in 3-wire mode: every write carries 9-bit words
+    /// with the D/C flag first, and the DC line is ignored.
+    pub fn three_wire_spi(&self) -> EmulatorSpi {
+        EmulatorSpi {
+            three_wire: true,
+            ..self.spi()
+        }
+    }
+
+    pub fn dc_pin(&self) -> EmulatorDcPin {
+        EmulatorDcPin {
+            emulator: self.clone(),
+        }
+    }
+
+    pub fn rst_pin(&self) -> EmulatorResetPin {
+        EmulatorResetPin {
+            emulator: self.clone(),
+        }
+    }
+
+    pub fn busy_pin(&self) -> EmulatorBusyPin {
+        EmulatorBusyPin {
+            emulator: self.clone(),
+        }
+    }
+
+    pub fn delay(&self) -> EmulatorDelay {
+        EmulatorDelay {
+            emulator: self.clone(),
+        }
+    }
+
+    pub fn controller(&self, index: usize) -> ControllerState {
+        self.state().controllers[index].snapshot()
+    }
+
+    /// All commands received by the first controller since creation, including those sent
+    /// during initialization.
+    pub fn commands(&self) -> Vec<CommandRecord> {
+        self.state().controllers[0].log.clone()
+    }
+
+    /// Command codes received by the first controller, without their data.
+    pub fn command_codes(&self) -> Vec<u8> {
+        self.state().controllers[0]
+            .log
+            .iter()
+            .map(|record| record.command)
+            .collect()
+    }
+
+    /// Clears the command log of every controller and the SPI transaction log.
+    pub fn clear_commands(&self) {
+        let mut state = self.state();
+        for controller in &mut state.controllers {
+            controller.log.clear();
+        }
+        state.transactions.clear();
+    }
+
+    /// Lengths of the writes of every SPI transaction, for all controllers.
+    pub fn spi_transactions(&self) -> Vec<Vec<usize>> {
+        self.state().transactions.clone()
+    }
+
+    pub fn violations(&self) -> Vec<Violation> {
+        self.state().violations.clone()
+    }
+
+    /// Resolution of the whole panel, as configured by the TRES command of every controller.
+    pub fn resolution(&self) -> (u16, u16) {
+        let state = self.state();
+        let width = state.controllers.iter().map(|c| c.width).sum();
+        let height = state.controllers.iter().map(|c| c.height).max();
+        (width, height.unwrap_or(0))
+    }
+
+    /// Partial window of the first controller.
+    pub fn window(&self) -> Option<Window> {
+        self.state().controllers[0].window
+    }
+
+    /// Number of refreshes performed by the first controller.
+    pub fn refresh_count(&self) -> usize {
+        self.state().controllers[0].refresh_count
+    }
+
+    /// Whether any controller has its panel power on.
+    pub fn is_powered(&self) -> bool {
+        self.state().controllers.iter().any(|c| c.powered)
+    }
+
+    /// Whether every controller is in deep sleep, which only a reset ends.
+    pub fn is_sleeping(&self) -> bool {
+        self.state().controllers.iter().all(|c| c.sleeping)
+    }
+
+    /// Virtual time elapsed through the delay handle.
+    pub fn elapsed_ms(&self) -> u64 {
+        self.state().now_ns / 1_000_000
+    }
+
+    /// Raw nibble shown on the panel at the given position.
+    pub fn raw_pixel(&self, x: u16, y: u16) -> Option<u8> {
+        let state = self.state();
+        let (controller, x) = state.locate(x)?;
+        State::raw_pixel(&controller.image, controller.width, controller.height, x, y)
+    }
+
+    /// Color shown on the panel at the given position.
+    pub fn pixel(&self, x: u16, y: u16) -> Option<E6Color> {
+        self.raw_pixel(x, y).map(E6Color::from)
+    }
+
+    /// Raw nibble stored in the controller RAM, which becomes visible on the next refresh.
+    pub fn ram_pixel(&self, x: u16, y: u16) -> Option<u8> {
+        let state = self.state();
+        let (controller, x) = state.locate(x)?;
+        State::raw_pixel(&controller.ram, controller.width, controller.height, x, y)
+    }
+}
+
+pub struct EmulatorSpi {
+    emulator: Emulator,
+    index: usize,
+    three_wire: bool,
+}
+
+pub struct EmulatorDcPin {
+    emulator: Emulator,
+}
+
+pub struct EmulatorResetPin {
+    emulator: Emulator,
+}
+
+pub struct EmulatorBusyPin {
+    emulator: Emulator,
+}
+
+pub struct EmulatorDelay {
+    emulator: Emulator,
+}
+
+impl EmulatorSpi {
+    fn log_transaction(&self, operations: &[Operation<'_, u8>]) {
+        let writes = operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Write(write) => Some(write.len()),
+                Operation::Transfer(_, write) => Some(write.len()),
+                _ => None,
+            })
+            .collect();
+        self.emulator.state().transactions.push(writes);
+    }
+
+    fn receive(&self, state: &mut State, bytes: &[u8]) {
+        if !self.three_wire {
+            state.write(self.index, bytes);
+            return;
+        }
+        // Trailing bits that don't make up a whole word are padding.
+        for word in 0..bytes.len() * 8 / 9 {
+            let bit = word * 9;
+            let pair =
+                u16::from_be_bytes([bytes[bit / 8], bytes.get(bit / 8 + 1).copied().unwrap_or(0)]);
+            let word = pair >> (7 - bit % 8) & 0x1FF;
+            state.data_mode = word & 0x100 != 0;
+            state.write(self.index, &[word as u8]);
+        }
+    }
+
+    fn execute(&mut self, operation: &mut Operation<'_, u8>) {
+        let mut state = self.emulator.state();
+        match operation {
+            Operation::Read(read) => read.fill(0),
+            Operation::Write(write) => self.receive(&mut state, write),
+            Operation::Transfer(read, write) => {
+                self.receive(&mut state, write);
+                read.fill(0);
+            }
+            Operation::TransferInPlace(buffer) => {
+                self.receive(&mut state, buffer);
+                buffer.fill(0);
+            }
+            Operation::DelayNs(ns) => state.now_ns += *ns as u64,
+        }
+    }
+}
+
+impl SpiErrorType for EmulatorSpi {
+    type Error = Infallible;
+}
+
+impl SpiDevice for EmulatorSpi {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        self.log_transaction(operations);
+        operations
+            .iter_mut()
+            .for_each(|operation| self.execute(operation));
+        Ok(())
+    }
+}
+
+impl DigitalErrorType for EmulatorDcPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for EmulatorDcPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.emulator.state().data_mode = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.emulator.state().data_mode = true;
+        Ok(())
+    }
+}
+
+impl DigitalErrorType for EmulatorResetPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for EmulatorResetPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.emulator.state().reset_asserted = true;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let mut state = self.emulator.state();
+        if state.reset_asserted {
+            state.reset_asserted = false;
+            state.reset();
+        }
+        Ok(())
+    }
+}
+
+impl DigitalErrorType for EmulatorBusyPin {
+    type Error = Infallible;
+}
+
+impl InputPin for EmulatorBusyPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.emulator.state().is_busy())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.emulator.state().is_busy())
+    }
+}
+
+impl DelayNs for EmulatorDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.emulator.state().now_ns += ns as u64;
+    }
+}
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+
+    impl embedded_hal_async::spi::SpiDevice for EmulatorSpi {
+        async fn transaction(
+            &mut self,
+            operations: &mut [Operation<'_, u8>],
+        ) -> Result<(), Self::Error> {
+            self.log_transaction(operations);
+            operations
+                .iter_mut()
+                .for_each(|operation| self.execute(operation));
+            Ok(())
+        }
+    }
+
+    impl embedded_hal_async::delay::DelayNs for EmulatorDelay {
+        async fn delay_ns(&mut self, ns: u32) {
+            self.emulator.state().now_ns += ns as u64;
+        }
+    }
+
+    /// Waiting for the line to go high stays pending while the controller is busy. The virtual
+    /// clock only advances through [`EmulatorDelay`], so the wait must be raced against a delay,
+    /// as the drivers do to enforce their busy timeouts.
+    /// The line only goes low in response to commands, so waiting for it to fall returns at once.
+    impl embedded_hal_async::digital::Wait for EmulatorBusyPin {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            core::future::poll_fn(|cx| {
+                if self.emulator.state().is_busy() {
+                    cx.waker().wake_by_ref();
+                    core::task::Poll::Pending
+                } else {
+                    core::task::Poll::Ready(Ok(()))
+                }
+            })
+            .await
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_high().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            self.wait_for_high().await
+        }
+    }
+}
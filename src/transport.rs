@@ -0,0 +1,408 @@
+//! How commands and data reach the controller.
+//!
+//! [`FourWire`] is the usual wiring, with a separate DC line telling commands from data.
+//! [`ThreeWire`] sends the D/C flag as the ninth bit of every SPI word instead, packed into
+//! bytes for 8-bit SPI peripherals. Over a [`DualSpi`], both become a [`DualTransport`] for
+//! panels split between two controllers. Other wirings, e.g. a DC line behind a GPIO expander
+//! that can't be an [`OutputPin`], implement [`Transport`] or [`AsyncTransport`] themselves.
+
+use crate::display::{Controller, Error};
+use crate::e6_display::{CommandCode, DataCommand, set_data_command};
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::{ErrorKind, ErrorType, Operation, SpiDevice};
+
+/// Sends commands and their parameters to the controller.
+pub trait Transport {
+    fn write_command(&mut self, command: CommandCode) -> Result<(), Error>;
+
+    /// Parameters or pixel data of the last command. May be called several times per command.
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// Sends the command while reading the status bytes the controller answers with.
+    /// Transports that can't read fill `status` with zeros, the drivers only log it.
+    fn write_command_with_status(
+        &mut self,
+        command: CommandCode,
+        status: &mut [u8],
+    ) -> Result<(), Error> {
+        self.write_command(command)?;
+        status.fill(0);
+        Ok(())
+    }
+}
+
+/// Transport to both controllers of a split panel, see
+/// [`E6DualDisplay`](crate::e6_dual_display::E6DualDisplay).
+pub trait DualTransport: Transport {
+    /// Sends the following commands and data to the controller.
+    fn select(&mut self, controller: Controller);
+}
+
+#[cfg(feature = "async")]
+pub trait AsyncTransport {
+    fn write_command(&mut self, command: CommandCode) -> impl Future<Output = Result<(), Error>>;
+
+    /// Parameters or pixel data of the last command. May be called several times per command.
+    fn write_data(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Error>>;
+
+    /// Sends the command while reading the status bytes the controller answers with.
+    /// Transports that can't read fill `status` with zeros, the drivers only log it.
+    fn write_command_with_status(
+        &mut self,
+        command: CommandCode,
+        status: &mut [u8],
+    ) -> impl Future<Output = Result<(), Error>> {
+        async move {
+            self.write_command(command).await?;
+            status.fill(0);
+            Ok(())
+        }
+    }
+}
+
+/// Most chunks sent in one SPI transaction.
+pub const MAX_CHUNKS_PER_TRANSACTION: usize = 64;
+
+/// Chunks sent in one SPI transaction unless configured otherwise.
+pub const DEFAULT_CHUNKS_PER_TRANSACTION: usize = 8;
+
+/// SPI device plus a DC line that is low for commands and high for data.
+///
+/// Data is sent in a single write by default. With [`FourWire::with_max_chunk_len`], longer
+/// data like the frame buffer is split into chunks that fit the DMA limits of the HAL. The
+/// chunks are sent in transactions of [`DEFAULT_CHUNKS_PER_TRANSACTION`] chunks, which keep
+/// chip select asserted. Between transactions it is released, which the controller takes
+/// like the gaps between the rows of a partial window: it keeps receiving the data of the
+/// last command. The async transport yields between transactions, so other tasks run during
+/// long uploads.
+pub struct FourWire<SPI, DC: OutputPin> {
+    spi: SPI,
+    dc_pin: DC,
+    max_chunk_len: usize,
+    chunks_per_transaction: usize,
+}
+
+impl<SPI, DC: OutputPin> FourWire<SPI, DC> {
+    pub fn new(spi: SPI, dc_pin: DC) -> Self {
+        Self {
+            spi,
+            dc_pin,
+            max_chunk_len: usize::MAX,
+            chunks_per_transaction: DEFAULT_CHUNKS_PER_TRANSACTION,
+        }
+    }
+
+    /// Limits the length of a single SPI write.
+    pub fn with_max_chunk_len(self, max_chunk_len: usize) -> Self {
+        Self {
+            max_chunk_len: max_chunk_len.max(1),
+            ..self
+        }
+    }
+
+    /// Sends up to `chunks_per_transaction` chunks, at most [`MAX_CHUNKS_PER_TRANSACTION`], in
+    /// one transaction. Fewer chunks release chip select and, in the async transport, yield
+    /// more often, 1 yields after every chunk.
+    pub fn with_chunks_per_transaction(self, chunks_per_transaction: usize) -> Self {
+        Self {
+            chunks_per_transaction: chunks_per_transaction.clamp(1, MAX_CHUNKS_PER_TRANSACTION),
+            ..self
+        }
+    }
+
+    /// Parts of `data` sent in one transaction each.
+    fn transactions<'a>(&self, data: &'a [u8]) -> core::slice::Chunks<'a, u8> {
+        data.chunks(
+            self.max_chunk_len
+                .saturating_mul(self.chunks_per_transaction),
+        )
+    }
+
+    /// Write operations of one transaction, and how many of them are used.
+    fn operations<'a>(
+        &self,
+        transaction: &'a [u8],
+    ) -> ([Operation<'a, u8>; MAX_CHUNKS_PER_TRANSACTION], usize) {
+        let mut operations = core::array::from_fn(|_| Operation::Write(&[]));
+        let mut len = 0;
+        for (operation, chunk) in operations
+            .iter_mut()
+            .zip(transaction.chunks(self.max_chunk_len))
+        {
+            *operation = Operation::Write(chunk);
+            len += 1;
+        }
+        (operations, len)
+    }
+
+    pub fn release(self) -> (SPI, DC) {
+        (self.spi, self.dc_pin)
+    }
+}
+
+impl<SPI: embedded_hal::spi::SpiDevice, DC: OutputPin> Transport for FourWire<SPI, DC> {
+    fn write_command(&mut self, command: CommandCode) -> Result<(), Error> {
+        set_data_command(&mut self.dc_pin, DataCommand::Command)?;
+        self.spi
+            .write(&[command as u8])
+            .map_err(Error::from_spi_error)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        set_data_command(&mut self.dc_pin, DataCommand::Data)?;
+        if data.len() <= self.max_chunk_len {
+            return self.spi.write(data).map_err(Error::from_spi_error);
+        }
+        for transaction in self.transactions(data) {
+            let (mut operations, len) = self.operations(transaction);
+            self.spi
+                .transaction(&mut operations[..len])
+                .map_err(Error::from_spi_error)?;
+        }
+        Ok(())
+    }
+
+    fn write_command_with_status(
+        &mut self,
+        command: CommandCode,
+        status: &mut [u8],
+    ) -> Result<(), Error> {
+        set_data_command(&mut self.dc_pin, DataCommand::Command)?;
+        self.spi
+            .transfer(status, &[command as u8])
+            .map_err(Error::from_spi_error)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<SPI: embedded_hal_async::spi::SpiDevice, DC: OutputPin> AsyncTransport for FourWire<SPI, DC> {
+    async fn write_command(&mut self, command: CommandCode) -> Result<(), Error> {
+        set_data_command(&mut self.dc_pin, DataCommand::Command)?;
+        self.spi
+            .write(&[command as u8])
+            .await
+            .map_err(Error::from_spi_error)
+    }
+
+    async fn write_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        set_data_command(&mut self.dc_pin, DataCommand::Data)?;
+        if data.len() <= self.max_chunk_len {
+            return self.spi.write(data).await.map_err(Error::from_spi_error);
+        }
+        for (index, transaction) in self.transactions(data).enumerate() {
+            if index > 0 {
+                yield_now().await;
+            }
+            let (mut operations, len) = self.operations(transaction);
+            self.spi
+                .transaction(&mut operations[..len])
+                .await
+                .map_err(Error::from_spi_error)?;
+        }
+        Ok(())
+    }
+
+    async fn write_command_with_status(
+        &mut self,
+        command: CommandCode,
+        status: &mut [u8],
+    ) -> Result<(), Error> {
+        set_data_command(&mut self.dc_pin, DataCommand::Command)?;
+        self.spi
+            .transfer(status, &[command as u8])
+            .await
+            .map_err(Error::from_spi_error)
+    }
+}
+
+/// Lets the executor run other tasks before continuing.
+#[cfg(feature = "async")]
+async fn yield_now() {
+    let mut yielded = false;
+    core::future::poll_fn(|cx| {
+        if yielded {
+            core::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            core::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Words packed per SPI write, 8 words fill exactly 9 bytes.
+const THREE_WIRE_WORDS: usize = 64;
+const THREE_WIRE_BYTES: usize = THREE_WIRE_WORDS / 8 * 9;
+
+/// 3-wire SPI with 9-bit words: the D/C flag (0 for commands, 1 for data) followed by the
+/// byte, most significant bit first.
+///
+/// The words are packed into an 8-bit stream and sent in writes of up to 64 words, so every
+/// write but the last of a command ends on a byte boundary. The last one is padded with zero
+/// bits, which the controller drops when chip select goes high. The data line is
+/// bidirectional in this mode, so status bytes aren't read back.
+pub struct ThreeWire<SPI> {
+    spi: SPI,
+    buffer: [u8; THREE_WIRE_BYTES],
+}
+
+impl<SPI> ThreeWire<SPI> {
+    pub fn new(spi: SPI) -> Self {
+        Self {
+            spi,
+            buffer: [0; THREE_WIRE_BYTES],
+        }
+    }
+
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+/// Packs the bytes as 9-bit words into `buffer` and returns the packed length.
+fn pack_nine_bit_words(data_command: DataCommand, bytes: &[u8], buffer: &mut [u8]) -> usize {
+    let flag = match data_command {
+        DataCommand::Data => 0x100u16,
+        DataCommand::Command => 0,
+    };
+    let len = (bytes.len() * 9).div_ceil(8);
+    buffer[..len].fill(0);
+    for (index, byte) in bytes.iter().enumerate() {
+        let bit = index * 9;
+        let word = (flag | *byte as u16) << (7 - bit % 8);
+        let [high, low] = word.to_be_bytes();
+        buffer[bit / 8] |= high;
+        buffer[bit / 8 + 1] |= low;
+    }
+    len
+}
+
+impl<SPI: embedded_hal::spi::SpiDevice> ThreeWire<SPI> {
+    fn write_words(&mut self, data_command: DataCommand, bytes: &[u8]) -> Result<(), Error> {
+        for chunk in bytes.chunks(THREE_WIRE_WORDS) {
+            let len = pack_nine_bit_words(data_command, chunk, &mut self.buffer);
+            self.spi
+                .write(&self.buffer[..len])
+                .map_err(Error::from_spi_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<SPI: embedded_hal::spi::SpiDevice> Transport for ThreeWire<SPI> {
+    fn write_command(&mut self, command: CommandCode) -> Result<(), Error> {
+        self.write_words(DataCommand::Command, &[command as u8])
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.write_words(DataCommand::Data, data)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<SPI: embedded_hal_async::spi::SpiDevice> ThreeWire<SPI> {
+    async fn write_words_async(
+        &mut self,
+        data_command: DataCommand,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        for chunk in bytes.chunks(THREE_WIRE_WORDS) {
+            let len = pack_nine_bit_words(data_command, chunk, &mut self.buffer);
+            self.spi
+                .write(&self.buffer[..len])
+                .await
+                .map_err(Error::from_spi_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<SPI: embedded_hal_async::spi::SpiDevice> AsyncTransport for ThreeWire<SPI> {
+    async fn write_command(&mut self, command: CommandCode) -> Result<(), Error> {
+        self.write_words_async(DataCommand::Command, &[command as u8])
+            .await
+    }
+
+    async fn write_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.write_words_async(DataCommand::Data, data).await
+    }
+}
+
+/// SPI devices of the two controllers of a split panel, with chip selects of their own. It
+/// writes to the selected controller, the primary one at first.
+pub struct DualSpi<PSPI, SSPI> {
+    primary: PSPI,
+    secondary: SSPI,
+    selected: Controller,
+}
+
+impl<PSPI, SSPI> DualSpi<PSPI, SSPI> {
+    pub fn new(primary: PSPI, secondary: SSPI) -> Self {
+        Self {
+            primary,
+            secondary,
+            selected: Controller::Primary,
+        }
+    }
+
+    pub fn select(&mut self, controller: Controller) {
+        self.selected = controller;
+    }
+
+    pub fn release(self) -> (PSPI, SSPI) {
+        (self.primary, self.secondary)
+    }
+}
+
+/// Error of the SPI device of either controller.
+#[derive(Debug)]
+pub enum DualSpiError<PERR, SERR> {
+    Primary(PERR),
+    Secondary(SERR),
+}
+
+impl<PERR: embedded_hal::spi::Error, SERR: embedded_hal::spi::Error> embedded_hal::spi::Error
+    for DualSpiError<PERR, SERR>
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Primary(error) => error.kind(),
+            Self::Secondary(error) => error.kind(),
+        }
+    }
+}
+
+impl<PSPI: ErrorType, SSPI: ErrorType> ErrorType for DualSpi<PSPI, SSPI> {
+    type Error = DualSpiError<PSPI::Error, SSPI::Error>;
+}
+
+impl<PSPI: SpiDevice, SSPI: SpiDevice> SpiDevice for DualSpi<PSPI, SSPI> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        match self.selected {
+            Controller::Primary => self
+                .primary
+                .transaction(operations)
+                .map_err(DualSpiError::Primary),
+            Controller::Secondary => self
+                .secondary
+                .transaction(operations)
+                .map_err(DualSpiError::Secondary),
+        }
+    }
+}
+
+impl<PSPI: SpiDevice, SSPI: SpiDevice, DC: OutputPin> DualTransport
+    for FourWire<DualSpi<PSPI, SSPI>, DC>
+{
+    fn select(&mut self, controller: Controller) {
+        self.spi.select(controller);
+    }
+}
+
+impl<PSPI: SpiDevice, SSPI: SpiDevice> DualTransport for ThreeWire<DualSpi<PSPI, SSPI>> {
+    fn select(&mut self, controller: Controller) {
+        self.spi.select(controller);
+    }
+}
@@ -0,0 +1,297 @@
+//! Typestate layer over the runtime drivers.
+//!
+//! [`Panel`] wraps any [`BlockingDisplay`] and encodes the controller lifecycle in its type:
+//! [`Panel::new`] returns an [`Uninitialized`] handle, [`Panel::initialize`] turns it into a
+//! [`Ready`] one, and only a ready panel can be drawn to or refreshed. [`AsyncPanel`] does the
+//! same for [`AsyncDisplay`]. A failed transition hands the panel back in its previous state
+//! together with the error, so the peripherals are never lost.
+
+#[cfg(feature = "async")]
+use crate::display::{AsyncDisplay, AsyncPartialUpdate};
+#[cfg(feature = "blocking")]
+use crate::display::{BlockingDisplay, PartialUpdate};
+use crate::display::{Color, Display, Error};
+use crate::e6_display::E6Color;
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+use embedded_graphics::Pixel;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{OriginDimensions, Size};
+use embedded_graphics::primitives::Rectangle;
+
+/// The controller has not been reset and configured yet.
+pub struct Uninitialized;
+/// The controller is configured and accepts drawing and refreshes.
+pub struct Ready;
+/// The controller is in deep sleep.
+pub struct Sleeping;
+
+/// Result of a state transition: the panel in the new state, or the panel in the old state
+/// together with the error that stopped the transition.
+pub type Transition<NEXT, PREVIOUS> = Result<NEXT, (PREVIOUS, Error)>;
+
+#[cfg(feature = "blocking")]
+pub struct Panel<D, STATE, C: Color = E6Color> {
+    display: D,
+    _state: PhantomData<(STATE, C)>,
+}
+
+#[cfg(feature = "blocking")]
+impl<D, STATE, C: Color> Panel<D, STATE, C> {
+    fn into_state<NEXT>(self) -> Panel<D, NEXT, C> {
+        Panel {
+            display: self.display,
+            _state: PhantomData,
+        }
+    }
+
+    /// Gives the wrapped driver back, leaving the lifecycle checks to the caller.
+    pub fn release(self) -> D {
+        self.display
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: BlockingDisplay<C>, C: Color> Panel<D, Uninitialized, C> {
+    pub fn new(display: D) -> Self {
+        Self {
+            display,
+            _state: PhantomData,
+        }
+    }
+
+    pub fn initialize(mut self) -> Transition<Panel<D, Ready, C>, Self> {
+        match self.display.initialize() {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err((self, error)),
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: BlockingDisplay<C>, C: Color> Panel<D, Ready, C> {
+    pub fn update(&mut self, iter: impl IntoIterator<Item = C>) -> Result<(), Error> {
+        self.display.update(iter)
+    }
+
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        self.display.refresh()
+    }
+
+    pub fn sleep(mut self) -> Transition<Panel<D, Sleeping, C>, Self> {
+        match self.display.sleep() {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err((self, error)),
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: BlockingDisplay<C> + PartialUpdate<C>, C: Color> Panel<D, Ready, C> {
+    pub fn partial_update(
+        &mut self,
+        iter: impl IntoIterator<Item = C>,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.display.partial_update(iter, horizontal, vertical)
+    }
+
+    pub fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.display.partial_refresh(horizontal, vertical)
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: BlockingDisplay<C>, C: Color> Panel<D, Sleeping, C> {
+    pub fn wake(mut self) -> Transition<Panel<D, Ready, C>, Self> {
+        match self.display.wake() {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err((self, error)),
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: Display<C>, C: Color, STATE> Display<C> for Panel<D, STATE, C> {
+    fn width(&self) -> u16 {
+        self.display.width()
+    }
+
+    fn height(&self) -> u16 {
+        self.display.height()
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: OriginDimensions, C: Color> OriginDimensions for Panel<D, Ready, C> {
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl<D: DrawTarget + OriginDimensions, C: Color> DrawTarget for Panel<D, Ready, C> {
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.display.draw_iter(pixels)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.display.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.clear(color)
+    }
+}
+
+#[cfg(feature = "async")]
+pub struct AsyncPanel<D, STATE, C: Color = E6Color> {
+    display: D,
+    _state: PhantomData<(STATE, C)>,
+}
+
+#[cfg(feature = "async")]
+impl<D, STATE, C: Color> AsyncPanel<D, STATE, C> {
+    fn into_state<NEXT>(self) -> AsyncPanel<D, NEXT, C> {
+        AsyncPanel {
+            display: self.display,
+            _state: PhantomData,
+        }
+    }
+
+    /// Gives the wrapped driver back, leaving the lifecycle checks to the caller.
+    pub fn release(self) -> D {
+        self.display
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: AsyncDisplay<C>, C: Color> AsyncPanel<D, Uninitialized, C> {
+    pub fn new(display: D) -> Self {
+        Self {
+            display,
+            _state: PhantomData,
+        }
+    }
+
+    pub async fn initialize(mut self) -> Transition<AsyncPanel<D, Ready, C>, Self> {
+        match self.display.initialize().await {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err((self, error)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: AsyncDisplay<C>, C: Color> AsyncPanel<D, Ready, C> {
+    pub async fn update(&mut self, iter: impl IntoIterator<Item = C>) -> Result<(), Error> {
+        self.display.update(iter).await
+    }
+
+    pub async fn refresh(&mut self) -> Result<(), Error> {
+        self.display.refresh().await
+    }
+
+    pub async fn sleep(mut self) -> Transition<AsyncPanel<D, Sleeping, C>, Self> {
+        match self.display.sleep().await {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err((self, error)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: AsyncDisplay<C> + AsyncPartialUpdate<C>, C: Color> AsyncPanel<D, Ready, C> {
+    pub async fn partial_update(
+        &mut self,
+        iter: impl IntoIterator<Item = C>,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.display
+            .partial_update(iter, horizontal, vertical)
+            .await
+    }
+
+    pub async fn partial_refresh(
+        &mut self,
+        horizontal: RangeInclusive<u16>,
+        vertical: RangeInclusive<u16>,
+    ) -> Result<(), Error> {
+        self.display.partial_refresh(horizontal, vertical).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: AsyncDisplay<C>, C: Color> AsyncPanel<D, Sleeping, C> {
+    pub async fn wake(mut self) -> Transition<AsyncPanel<D, Ready, C>, Self> {
+        match self.display.wake().await {
+            Ok(()) => Ok(self.into_state()),
+            Err(error) => Err((self, error)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: Display<C>, C: Color, STATE> Display<C> for AsyncPanel<D, STATE, C> {
+    fn width(&self) -> u16 {
+        self.display.width()
+    }
+
+    fn height(&self) -> u16 {
+        self.display.height()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: OriginDimensions, C: Color> OriginDimensions for AsyncPanel<D, Ready, C> {
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<D: DrawTarget + OriginDimensions, C: Color> DrawTarget for AsyncPanel<D, Ready, C> {
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.display.draw_iter(pixels)
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        self.display.fill_contiguous(area, colors)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.fill_solid(area, color)
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.display.clear(color)
+    }
+}